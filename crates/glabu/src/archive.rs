@@ -0,0 +1,369 @@
+//! Extraction of downloaded package archives (tar, tar.gz, tar.xz and zip).
+//!
+//! Entries are never written outside of the output directory:
+//! absolute paths and `..` components are rejected, and so are links pointing outside
+//! and entries below a symlink extracted earlier.
+
+use std::fs::File;
+use std::io::Read;
+use std::path::{Component, Path, PathBuf};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveKind {
+    Tar,
+    TarGz,
+    TarXz,
+    Zip,
+}
+
+impl ArchiveKind {
+    /// Guesses the archive kind from a file name, `None` if it is not an archive.
+    pub fn from_file_name(file_name: &str) -> Option<Self> {
+        let file_name = file_name.to_lowercase();
+        if file_name.ends_with(".tar.gz") || file_name.ends_with(".tgz") {
+            Some(ArchiveKind::TarGz)
+        } else if file_name.ends_with(".tar.xz") || file_name.ends_with(".txz") {
+            Some(ArchiveKind::TarXz)
+        } else if file_name.ends_with(".tar") {
+            Some(ArchiveKind::Tar)
+        } else if file_name.ends_with(".zip") {
+            Some(ArchiveKind::Zip)
+        } else {
+            None
+        }
+    }
+}
+
+/// Extracts `archive` into `output_dir`, dropping the first `strip_components`
+/// path components of every entry (like `tar --strip-components`).
+///
+/// Returns the paths of the extracted files.
+pub fn extract(
+    archive: &Path,
+    kind: ArchiveKind,
+    output_dir: &Path,
+    strip_components: usize,
+) -> Result<Vec<PathBuf>, Box<dyn std::error::Error>> {
+    std::fs::create_dir_all(output_dir)?;
+    let file = File::open(archive)?;
+    match kind {
+        ArchiveKind::Tar => extract_tar(file, output_dir, strip_components),
+        ArchiveKind::TarGz => extract_tar(
+            flate2::read::GzDecoder::new(file),
+            output_dir,
+            strip_components,
+        ),
+        ArchiveKind::TarXz => extract_tar(
+            xz2::read::XzDecoder::new(file),
+            output_dir,
+            strip_components,
+        ),
+        ArchiveKind::Zip => extract_zip(file, output_dir, strip_components),
+    }
+}
+
+/// Reads the first regular file in `archive` whose path satisfies `matches`,
+/// e.g. the metadata inside a package archive.
+pub fn read_entry(
+    archive: &Path,
+    kind: ArchiveKind,
+    matches: impl Fn(&Path) -> bool,
+) -> Result<Option<Vec<u8>>, Box<dyn std::error::Error>> {
+    let file = File::open(archive)?;
+    match kind {
+        ArchiveKind::Tar => read_tar_entry(file, matches),
+        ArchiveKind::TarGz => read_tar_entry(flate2::read::GzDecoder::new(file), matches),
+        ArchiveKind::TarXz => read_tar_entry(xz2::read::XzDecoder::new(file), matches),
+        ArchiveKind::Zip => {
+            let mut archive = zip::ZipArchive::new(file)?;
+            for i in 0..archive.len() {
+                let mut entry = archive.by_index(i)?;
+                if entry.is_file() && matches(Path::new(entry.name())) {
+                    let mut content = vec![];
+                    entry.read_to_end(&mut content)?;
+                    return Ok(Some(content));
+                }
+            }
+            Ok(None)
+        }
+    }
+}
+
+/// Reads the first regular file of a tar stream whose path satisfies `matches`.
+pub fn read_tar_entry<R: Read>(
+    reader: R,
+    matches: impl Fn(&Path) -> bool,
+) -> Result<Option<Vec<u8>>, Box<dyn std::error::Error>> {
+    let mut archive = tar::Archive::new(reader);
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        if entry.header().entry_type().is_file() && matches(&entry.path()?) {
+            let mut content = vec![];
+            entry.read_to_end(&mut content)?;
+            return Ok(Some(content));
+        }
+    }
+    Ok(None)
+}
+
+/// Maps an entry path to its target below `output_dir`.
+///
+/// Returns `None` if nothing is left after stripping components,
+/// and an error if the entry would escape `output_dir`.
+pub fn entry_target(
+    output_dir: &Path,
+    entry_path: &Path,
+    strip_components: usize,
+) -> Result<Option<PathBuf>, Box<dyn std::error::Error>> {
+    let mut relative = PathBuf::new();
+    let mut skipped = 0;
+    for component in entry_path.components() {
+        match component {
+            Component::Normal(_) if skipped < strip_components => skipped += 1,
+            Component::Normal(part) => relative.push(part),
+            Component::CurDir => {}
+            Component::ParentDir | Component::RootDir | Component::Prefix(_) => {
+                return Err(format!(
+                    "UnsafeArchiveEntry: {} points outside of the output directory",
+                    entry_path.display()
+                )
+                .into());
+            }
+        }
+    }
+    if relative.as_os_str().is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(output_dir.join(relative)))
+}
+
+/// Checks that a relative link target stays inside the archive,
+/// `depth` being the number of directories between the output directory and the link.
+fn check_link_target(
+    entry_path: &Path,
+    link_target: &Path,
+    depth: usize,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut level = depth as isize;
+    for component in link_target.components() {
+        match component {
+            Component::Normal(_) => level += 1,
+            Component::CurDir => {}
+            Component::ParentDir => level -= 1,
+            Component::RootDir | Component::Prefix(_) => level = -1,
+        }
+        if level < 0 {
+            return Err(format!(
+                "UnsafeArchiveEntry: link {} -> {} points outside of the output directory",
+                entry_path.display(),
+                link_target.display()
+            )
+            .into());
+        }
+    }
+    Ok(())
+}
+
+/// Fails if one of the directories between `output_dir` and `target` is a symlink.
+/// The link targets are only checked lexically, so resolving a chain of links
+/// could still lead outside of `output_dir`.
+fn check_symlink_parents(
+    output_dir: &Path,
+    target: &Path,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut path = output_dir.to_path_buf();
+    let Some(parent) = target.strip_prefix(output_dir)?.parent() else {
+        return Ok(());
+    };
+    for component in parent.components() {
+        path.push(component);
+        if path
+            .symlink_metadata()
+            .is_ok_and(|metadata| metadata.file_type().is_symlink())
+        {
+            return Err(format!(
+                "UnsafeArchiveEntry: {} is below the symlink {}",
+                target.display(),
+                path.display()
+            )
+            .into());
+        }
+    }
+    Ok(())
+}
+
+/// Creates the parent directories of `target` and removes an existing file or link,
+/// so that nothing is written through a previously extracted symlink.
+fn prepare_target(output_dir: &Path, target: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    check_symlink_parents(output_dir, target)?;
+    if let Some(parent) = target.parent() {
+        std::fs::create_dir_all(parent)?;
+        if !parent
+            .canonicalize()?
+            .starts_with(output_dir.canonicalize()?)
+        {
+            return Err(format!(
+                "UnsafeArchiveEntry: {} is outside of the output directory",
+                target.display()
+            )
+            .into());
+        }
+    }
+    if target
+        .symlink_metadata()
+        .is_ok_and(|metadata| !metadata.is_dir())
+    {
+        std::fs::remove_file(target)?;
+    }
+    Ok(())
+}
+
+fn extract_tar<R: Read>(
+    reader: R,
+    output_dir: &Path,
+    strip_components: usize,
+) -> Result<Vec<PathBuf>, Box<dyn std::error::Error>> {
+    let mut archive = tar::Archive::new(reader);
+    let mut extracted = vec![];
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let entry_path = entry.path()?.to_path_buf();
+        let Some(target) = entry_target(output_dir, &entry_path, strip_components)? else {
+            continue;
+        };
+        let entry_type = entry.header().entry_type();
+        if entry_type.is_dir() {
+            check_symlink_parents(output_dir, &target)?;
+            std::fs::create_dir_all(&target)?;
+            continue;
+        }
+        if entry_type.is_hard_link() {
+            eprintln!("Skipping hard link {}", entry_path.display());
+            continue;
+        }
+        if entry_type.is_symlink() {
+            let link_target = entry
+                .link_name()?
+                .ok_or("Symlink without target")?
+                .to_path_buf();
+            let depth = target
+                .strip_prefix(output_dir)?
+                .components()
+                .count()
+                .saturating_sub(1);
+            check_link_target(&entry_path, &link_target, depth)?;
+        }
+        prepare_target(output_dir, &target)?;
+        entry.unpack(&target)?;
+        extracted.push(target);
+    }
+    Ok(extracted)
+}
+
+fn extract_zip(
+    file: File,
+    output_dir: &Path,
+    strip_components: usize,
+) -> Result<Vec<PathBuf>, Box<dyn std::error::Error>> {
+    let mut archive = zip::ZipArchive::new(file)?;
+    let mut extracted = vec![];
+    for i in 0..archive.len() {
+        let mut entry = archive.by_index(i)?;
+        let entry_path = PathBuf::from(entry.name());
+        let Some(target) = entry_target(output_dir, &entry_path, strip_components)? else {
+            continue;
+        };
+        if entry.is_dir() {
+            check_symlink_parents(output_dir, &target)?;
+            std::fs::create_dir_all(&target)?;
+            continue;
+        }
+        prepare_target(output_dir, &target)?;
+        let mut output = File::create(&target)?;
+        std::io::copy(&mut entry, &mut output)?;
+        #[cfg(unix)]
+        if let Some(mode) = entry.unix_mode() {
+            use std::os::unix::fs::PermissionsExt;
+            // no setuid, setgid or sticky bits from the archive
+            std::fs::set_permissions(&target, std::fs::Permissions::from_mode(mode & 0o777))?;
+        }
+        extracted.push(target);
+    }
+    Ok(extracted)
+}
+
+#[cfg(test)]
+mod archive_tests {
+    use super::*;
+
+    #[test]
+    fn test_entry_target() -> Result<(), Box<dyn std::error::Error>> {
+        let out = Path::new("/out");
+        assert_eq!(
+            entry_target(out, Path::new("./pkg-1.0/bin/tool"), 1)?,
+            Some(PathBuf::from("/out/bin/tool"))
+        );
+        assert_eq!(entry_target(out, Path::new("pkg-1.0/"), 1)?, None);
+        assert!(entry_target(out, Path::new("pkg/../../etc/passwd"), 0).is_err());
+        assert!(entry_target(out, Path::new("/etc/passwd"), 0).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_check_link_target() {
+        let entry = Path::new("bin/tool");
+        assert!(check_link_target(entry, Path::new("../lib/tool"), 1).is_ok());
+        assert!(check_link_target(entry, Path::new("../../etc/passwd"), 1).is_err());
+        assert!(check_link_target(entry, Path::new("/etc/passwd"), 1).is_err());
+    }
+
+    #[test]
+    fn test_extract_tar_symlink_escape() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = std::env::temp_dir().join(format!("glabu-archive-{}", std::process::id()));
+        let out = dir.join("out");
+        std::fs::create_dir_all(&out)?;
+        let mut builder = tar::Builder::new(vec![]);
+        let mut header = tar::Header::new_gnu();
+        header.set_entry_type(tar::EntryType::Directory);
+        header.set_mode(0o755);
+        header.set_size(0);
+        builder.append_data(&mut header, "a/", std::io::empty())?;
+        for link in ["a/b", "a/b/c"] {
+            let mut header = tar::Header::new_gnu();
+            header.set_entry_type(tar::EntryType::Symlink);
+            header.set_size(0);
+            builder.append_link(&mut header, link, "..")?;
+        }
+        let content = b"escaped";
+        let mut header = tar::Header::new_gnu();
+        header.set_mode(0o644);
+        header.set_size(content.len() as u64);
+        builder.append_data(&mut header, "a/b/c/escaped.txt", &content[..])?;
+        let archive = builder.into_inner()?;
+
+        let result = extract_tar(&archive[..], &out, 0);
+        let escaped = dir.join("escaped.txt").exists() || out.join("escaped.txt").exists();
+        std::fs::remove_dir_all(&dir)?;
+        assert!(
+            result
+                .unwrap_err()
+                .to_string()
+                .contains("UnsafeArchiveEntry")
+        );
+        assert!(!escaped);
+        Ok(())
+    }
+
+    #[test]
+    fn test_archive_kind() {
+        assert_eq!(
+            ArchiveKind::from_file_name("glabu-1.0.TGZ"),
+            Some(ArchiveKind::TarGz)
+        );
+        assert_eq!(
+            ArchiveKind::from_file_name("glabu.tar.xz"),
+            Some(ArchiveKind::TarXz)
+        );
+        assert_eq!(ArchiveKind::from_file_name("glabu-x86_64"), None);
+    }
+}
@@ -1,11 +1,46 @@
+use std::io::Read;
 use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
 
+use crate::endpoints::PrintOutput;
 use crate::endpoints::{
-    packages::{GenericPackageOp, ProjectPackageListOp},
+    checksums::PackageVerifyOp,
+    copy::PackageCopyOp,
+    debian::DebianOp,
+    helm::HelmOp,
+    install::BinaryInstallOp,
+    npm::NpmOp,
+    packages::{
+        GenericPackageOp, PackageStatus, PackageType, ProjectPackageListOp, UploadStatus,
+        VersionStrategy, packages_find, split_package_spec,
+    },
     projects::{ProjectCreate, ProjectDelete, ProjectForkPrivate, projects_search},
+    pypi::PypiOp,
+    registry::{PruneRules, RegistryOp},
+    releases::{ProjectReleasesGet, ReleaseCreate},
+    rpm::RpmOp,
+    setup::{GitlabInstance, gitlab_token, httpclient},
+    sync::PackageSyncOp,
+    terraform::TerraformModuleOp,
+    usage::{PackageUsageOp, ReportFormat},
+    watch::PackageWatchOp,
 };
 use clap::Parser;
 
+fn is_url(project: &str) -> bool {
+    project.starts_with("https://") || project.starts_with("http://")
+}
+
+/// Reads a minisign secret key, decrypting it with the password from $GLABU_MINISIGN_PASSWORD.
+fn read_signing_key(
+    path: Option<PathBuf>,
+) -> Result<Option<SecretKey>, Box<dyn std::error::Error>> {
+    let password = std::env::var(signing::PASSWORD_ENV).ok();
+    path.map(|path| SecretKey::from_file(path, password.as_deref()))
+        .transpose()
+}
+
 fn encode_project_id(project: &str) -> String {
     let mut project_id = project.to_string();
     if project_id.contains('/') {
@@ -16,7 +51,60 @@ fn encode_project_id(project: &str) -> String {
 
 use clap::Subcommand;
 
-use crate::models::ProjectVisibility;
+use crate::models::{ContainerExpirationPolicyAttributes, ProjectVisibility};
+use crate::oci::{self, ImageReference, OciLayout, RegistryClient, Root};
+use crate::signing::{self, SecretKey};
+
+#[derive(Subcommand, Debug)]
+pub enum DistTagAction {
+    /// Point a tag at a version
+    Add {
+        /// Package and version, for example: @scope/name@1.4.0
+        package_spec: String,
+        /// Tag to add
+        #[arg(default_value = "latest")]
+        tag: String,
+    },
+    /// Remove a tag
+    Rm {
+        /// Name of the package
+        package_name: String,
+        /// Tag to remove
+        tag: String,
+    },
+    /// List the tags of a package
+    Ls {
+        /// Name of the package
+        package_name: String,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum RegistryPolicyAction {
+    /// Show the cleanup policy
+    Show,
+    /// Change the cleanup policy, options left out are not changed
+    Set {
+        /// Turn the policy on or off
+        #[arg(long)]
+        enabled: Option<bool>,
+        /// How often the policy runs: 1d, 7d, 14d, 1month or 3month
+        #[arg(long)]
+        cadence: Option<String>,
+        /// Number of tags to keep per image: 1, 5, 10, 25, 50 or 100
+        #[arg(long)]
+        keep_n: Option<u32>,
+        /// Delete tags older than: 7d, 14d, 30d or 90d
+        #[arg(long)]
+        older_than: Option<String>,
+        /// Delete tags whose name matches this regex
+        #[arg(long)]
+        name_regex: Option<String>,
+        /// Keep tags whose name matches this regex
+        #[arg(long)]
+        name_regex_keep: Option<String>,
+    },
+}
 
 /// GitLab Utility (glabu) - A command-line tool for interacting with GitLab api v4
 #[derive(Parser, Debug)]
@@ -68,27 +156,61 @@ pub enum Commands {
     },
     /// Download package file(s)
     PackageDownload {
-        /// Full path to the project, for example: owner/project
+        /// Full path to the project, for example: owner/project,
+        /// or a package url like https://gitlab.com/owner/project/-/packages/12345
+        /// or https://gitlab.com/api/v4/projects/owner%2Fproject/packages/generic/name/version/file
         project: String,
-        /// Name of the package
+        /// Name of the package, required unless a package url is given
         #[arg(short = 'n', long)]
-        package_name: String,
+        package_name: Option<String>,
         /// Version of the package
         #[arg(short = 'v', long)]
         package_version: Option<String>,
         #[arg(short, long, default_value_t = false)]
         latest: bool,
+        /// How to pick the version when none is given: created-at, semver-max,
+        /// or a semver requirement such as ^1.4 or ~2.0
+        #[arg(short = 's', long, default_value_t = VersionStrategy::CreatedAt)]
+        strategy: VersionStrategy,
+        /// Filter packages by type, e.g. generic, npm, pypi, debian or rpm
+        #[arg(short = 't', long, value_parser = PackageType::from_str)]
+        package_type: Option<PackageType>,
         /// Specify the package file to download
         #[arg(short = 'f', long)]
         package_file: Option<String>,
         /// Filename regex to filter files
         #[arg(short = 'r', long)]
         regex: Option<String>,
-        /// Output file directory
-        #[arg(short = 'o', long, default_value = "/tmp")]
-        output_dir: PathBuf,
+        /// Output directory, output file path (a single matching file), or - for stdout
+        #[arg(
+            short = 'o',
+            long,
+            visible_alias = "output-dir",
+            default_value = "/tmp"
+        )]
+        output: PathBuf,
+        /// Path of each file inside the output directory, with the placeholders
+        /// {project}, {name}, {version}, {file} and {sha256}, e.g. {name}/{version}/{file}
+        #[arg(long)]
+        output_template: Option<String>,
+        /// Fail if a target file already exists with different content
+        #[arg(long, default_value_t = false)]
+        no_clobber: bool,
+        /// Unpack tar, tar.gz, tar.xz and zip files into the output directory
+        #[arg(short = 'x', long, default_value_t = false)]
+        extract: bool,
+        /// Number of leading path components to drop when extracting
+        #[arg(long, default_value_t = 0, requires = "extract")]
+        strip_components: usize,
+        /// Trusted minisign public key (file or base64) to verify signatures with,
+        /// defaults to $GLABU_PUBLIC_KEY
+        #[arg(long)]
+        public_key: Option<String>,
+        /// Refuse files without a valid signature
+        #[arg(long, default_value_t = false)]
+        require_signature: bool,
     },
-    /// Upload a single package file
+    /// Upload package files
     PackageUpload {
         /// Full path to the project, for example: owner/project
         project: String,
@@ -98,11 +220,39 @@ pub enum Commands {
         /// Version of the package
         #[arg(short = 'v', long)]
         package_version: String,
-        /// Specify the package file to upload
-        #[arg(short = 'f', long)]
-        file_path: String,
+        /// Specify the package files to upload
+        #[arg(short = 'f', long, num_args = 1.., required = true)]
+        file_path: Vec<String>,
+        /// File name in the registry, only when uploading a single file
         #[arg(short = 'm', long)]
         file_name: Option<String>,
+        /// Also upload a SHA256SUMS file listing every file of the package version
+        #[arg(long, default_value_t = false)]
+        sha256sums: bool,
+        /// Package status, hidden keeps the version out of the UI until `package-publish`
+        #[arg(long, value_enum)]
+        status: Option<UploadStatus>,
+        /// Minisign secret key to sign the file with, the signature is uploaded as <file>.minisig.
+        /// The password of an encrypted key is read from $GLABU_MINISIGN_PASSWORD
+        #[arg(long)]
+        sign_key: Option<PathBuf>,
+    },
+    /// List the packages of a project, e.g. all debian or rpm packages
+    PackageList {
+        /// Full path to the project, for example: owner/project
+        project: String,
+        /// Filter packages by name, matched fuzzily
+        #[arg(short = 'n', long)]
+        package_name: Option<String>,
+        /// Filter packages by version
+        #[arg(short = 'v', long)]
+        package_version: Option<String>,
+        /// Filter packages by type, e.g. generic, npm, pypi, debian or rpm
+        #[arg(short = 't', long, value_parser = PackageType::from_str)]
+        package_type: Option<PackageType>,
+        /// Filter packages by status, e.g. hidden
+        #[arg(long, value_enum)]
+        status: Option<PackageStatus>,
     },
     /// List files of a given package (with a given version)
     PackageFileList {
@@ -114,7 +264,430 @@ pub enum Commands {
         /// Version of the package
         #[arg(short = 'v', long)]
         package_version: String,
+        /// Filter packages by type, e.g. generic, npm, pypi, debian or rpm
+        #[arg(short = 't', long, value_parser = PackageType::from_str)]
+        package_type: Option<PackageType>,
+    },
+    /// Find packages by name in a group (including subgroups) or in all of your groups
+    PackageFind {
+        /// Name of the package, matched fuzzily
+        package_name: String,
+        /// Group to search in, defaults to all top level groups you are a member of;
+        /// projects in personal namespaces are not searched
+        #[arg(short = 'g', long)]
+        group: Option<String>,
+        /// Filter packages by type, e.g. generic, npm, pypi, debian or rpm
+        #[arg(short = 't', long, value_parser = PackageType::from_str)]
+        package_type: Option<PackageType>,
+        /// How to pick the latest version: created-at, semver-max, or a semver requirement
+        #[arg(short = 's', long, default_value_t = VersionStrategy::CreatedAt)]
+        strategy: VersionStrategy,
+    },
+    /// Make a hidden package version visible by uploading its final file and/or SHA256SUMS
+    PackagePublish {
+        /// Full path to the project, for example: owner/project
+        project: String,
+        /// Name of the package
+        #[arg(short = 'n', long)]
+        package_name: String,
+        /// Version of the package
+        #[arg(short = 'v', long)]
+        package_version: String,
+        /// Last file of the version, uploaded visible
+        #[arg(short = 'f', long, required_unless_present = "sha256sums")]
+        file_path: Option<PathBuf>,
+        /// Upload a SHA256SUMS file listing every file of the version
+        #[arg(long, default_value_t = false)]
+        sha256sums: bool,
+        /// Minisign secret key to sign the uploaded files with,
+        /// the password of an encrypted key is read from $GLABU_MINISIGN_PASSWORD
+        #[arg(long)]
+        sign_key: Option<PathBuf>,
+    },
+    /// Check the SHA256SUMS file of a package version against the registry and local files
+    PackageVerify {
+        /// Full path to the project, for example: owner/project
+        project: String,
+        /// Name of the package
+        #[arg(short = 'n', long)]
+        package_name: String,
+        /// Version of the package
+        #[arg(short = 'v', long)]
+        package_version: Option<String>,
+        /// How to pick the version when none is given: created-at, semver-max,
+        /// or a semver requirement such as ^1.4 or ~2.0
+        #[arg(short = 's', long, default_value_t = VersionStrategy::CreatedAt)]
+        strategy: VersionStrategy,
+        /// Directory with downloaded files to verify as well
+        #[arg(short = 'd', long)]
+        dir: Option<PathBuf>,
+    },
+    /// Copy every file of a package version to another project, optionally on another instance
+    PackageCopy {
+        /// Full path to the source project, for example: owner/staging
+        from_project: String,
+        /// Full path to the target project, for example: owner/prebuilt
+        to_project: String,
+        /// Name of the package
+        #[arg(short = 'n', long)]
+        package_name: String,
+        /// Version of the package
+        #[arg(short = 'v', long)]
+        package_version: Option<String>,
+        /// How to pick the version when none is given: created-at, semver-max,
+        /// or a semver requirement such as ^1.4 or ~2.0
+        #[arg(short = 's', long, default_value_t = VersionStrategy::CreatedAt)]
+        strategy: VersionStrategy,
+        /// Version in the target project, defaults to the source version
+        #[arg(long)]
+        to_version: Option<String>,
+        /// Host of the target instance, for example: https://gitlab.example.com
+        #[arg(long)]
+        to_host: Option<String>,
+        /// Environment variable holding the token for the target instance
+        #[arg(long, default_value = "GITLAB_TOKEN", requires = "to_host")]
+        to_token_env: String,
+    },
+    /// Poll a package and download every new version, optionally running a hook
+    PackageWatch {
+        /// Full path to the project, for example: owner/project
+        project: String,
+        /// Name of the package
+        #[arg(short = 'n', long)]
+        package_name: String,
+        /// How to pick the latest version: created-at, semver-max,
+        /// or a semver requirement such as ^1.4 or ~2.0
+        #[arg(short = 's', long, default_value_t = VersionStrategy::CreatedAt)]
+        strategy: VersionStrategy,
+        /// Regex for the files to download, all files by default
+        #[arg(short = 'r', long)]
+        regex: Option<String>,
+        /// Directory to download new versions into
+        #[arg(short = 'o', long, default_value = ".")]
+        output: PathBuf,
+        /// Shell command to run after downloading a new version, it gets GLABU_PACKAGE_NAME,
+        /// GLABU_PACKAGE_VERSION, GLABU_PREVIOUS_VERSION, GLABU_OUTPUT_DIR and
+        /// GLABU_PACKAGE_FILES (one path per line) in its environment
+        #[arg(long)]
+        hook: Option<String>,
+        /// Version that is already deployed, defaults to the latest version at startup
+        #[arg(long)]
+        since: Option<String>,
+        /// Seconds between checks
+        #[arg(long, default_value_t = 60)]
+        interval: u64,
+        /// Upper bound in seconds for the interval, which doubles while nothing changes
+        #[arg(long, default_value_t = 900)]
+        max_interval: u64,
+    },
+    /// Report the storage used by the packages of a project or group
+    PackageUsage {
+        /// Full path to the project or group, for example: owner/project
+        path: String,
+        /// The path is a group, packages of its subgroups are included
+        #[arg(short = 'g', long, default_value_t = false)]
+        group: bool,
+        /// Filter packages by type, e.g. generic, npm, pypi, debian or rpm
+        #[arg(short = 't', long, value_parser = PackageType::from_str)]
+        package_type: Option<PackageType>,
+        /// Number of largest versions to show, 0 for all
+        #[arg(long, default_value_t = 20)]
+        top: usize,
+        /// Show the storage added per month instead of the largest versions
+        #[arg(long, default_value_t = false, conflicts_with = "by_name")]
+        growth: bool,
+        /// Show the storage used per package name instead of the largest versions
+        #[arg(long, default_value_t = false)]
+        by_name: bool,
+        #[arg(short = 'f', long, value_enum, default_value_t = ReportFormat::Table)]
+        format: ReportFormat,
+    },
+    /// Upload Python wheels or sdists to the PyPI registry of a project
+    PypiUpload {
+        /// Full path to the project, for example: owner/project
+        project: String,
+        /// Wheels (.whl) or sdists (.tar.gz, .zip) to upload
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+    },
+    /// Download a Python package from the PyPI registry of a project
+    PypiDownload {
+        /// Full path to the project, for example: owner/project
+        project: String,
+        /// Name of the Python package
+        #[arg(short = 'n', long)]
+        package_name: String,
+        /// Version of the package
+        #[arg(short = 'v', long)]
+        package_version: Option<String>,
+        /// How to pick the version when none is given: created-at, semver-max,
+        /// or a semver requirement such as ^1.4 or ~2.0
+        #[arg(short = 's', long, default_value_t = VersionStrategy::CreatedAt)]
+        strategy: VersionStrategy,
+        /// Filename regex to filter files, e.g. py3-none-any to only get the wheel
+        #[arg(short = 'r', long)]
+        regex: Option<String>,
+        /// Output directory
+        #[arg(short = 'o', long, default_value = ".")]
+        output: PathBuf,
+    },
+    /// Publish an npm pack tarball to the npm registry of a project
+    NpmPublish {
+        /// Full path to the project, for example: owner/project
+        project: String,
+        /// Tarball created by npm pack
+        tarball: PathBuf,
+        /// Dist-tag to publish under
+        #[arg(long, default_value = "latest")]
+        tag: String,
+    },
+    /// Manage the dist-tags of an npm package
+    NpmDistTag {
+        /// Full path to the project, for example: owner/project
+        project: String,
+        #[command(subcommand)]
+        action: DistTagAction,
+    },
+    /// Push a packaged Helm chart to the Helm registry of a project
+    HelmPush {
+        /// Full path to the project, for example: owner/project
+        project: String,
+        /// Chart archive created by helm package
+        chart: PathBuf,
+        #[arg(short = 'c', long, default_value = "stable")]
+        channel: String,
+    },
+    /// Show the charts in a channel of the Helm registry of a project
+    HelmIndex {
+        /// Full path to the project, for example: owner/project
+        project: String,
+        #[arg(short = 'c', long, default_value = "stable")]
+        channel: String,
+    },
+    /// Download a chart from the Helm registry of a project
+    HelmPull {
+        /// Full path to the project, for example: owner/project
+        project: String,
+        /// Name of the chart
+        #[arg(short = 'n', long)]
+        chart_name: String,
+        /// Version of the chart
+        #[arg(short = 'v', long)]
+        chart_version: Option<String>,
+        /// How to pick the version when none is given: created-at, semver-max,
+        /// or a semver requirement such as ^1.4 or ~2.0
+        #[arg(short = 's', long, default_value_t = VersionStrategy::CreatedAt)]
+        strategy: VersionStrategy,
+        #[arg(short = 'c', long, default_value = "stable")]
+        channel: String,
+        /// Output directory, output file path, or - for stdout
+        #[arg(short = 'o', long, default_value = ".")]
+        output: PathBuf,
+    },
+    /// Package a module directory and publish it to the Terraform module registry of a project.
+    /// Files matched by .terraformignore are left out
+    TerraformModulePublish {
+        /// Full path to the project, for example: owner/project
+        project: String,
+        /// Directory of the module
+        dir: PathBuf,
+        /// Name of the module
+        #[arg(short = 'n', long)]
+        name: String,
+        /// Provider of the module, e.g. aws, google or local
+        #[arg(short = 's', long)]
+        system: String,
+        /// Version of the module, e.g. 1.2.0
+        #[arg(short = 'v', long)]
+        version: String,
+    },
+    /// List the modules in the Terraform module registry of a project, newest first
+    TerraformModuleList {
+        /// Full path to the project, for example: owner/project
+        project: String,
+        /// Filter modules by name, matched fuzzily
+        #[arg(short = 'n', long)]
+        name: Option<String>,
+    },
+    /// Upload .deb and .changes files to the Debian registry of a project.
+    /// The files listed in a .changes file are uploaded along with it
+    DebianUpload {
+        /// Full path to the project, for example: owner/project
+        project: String,
+        /// Files to upload
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+        /// Distribution to publish .deb files into, e.g. bookworm. Without it, they wait
+        /// for a .changes file
+        #[arg(short = 'd', long)]
+        distribution: Option<String>,
+        #[arg(short = 'c', long, default_value = "main")]
+        component: String,
+        /// Create the distribution in the project if it does not exist
+        #[arg(long, default_value_t = false)]
+        create_distribution: bool,
+    },
+    /// Upload .rpm files to the RPM registry of a project
+    RpmUpload {
+        /// Full path to the project, for example: owner/project
+        project: String,
+        /// Files to upload
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+    },
+    /// List the repositories of the container registry of a project
+    RegistryRepos {
+        /// Full path to the project, for example: owner/project
+        project: String,
+    },
+    /// List the tags of a container registry repository
+    RegistryTags {
+        /// Full path to the project, for example: owner/project
+        project: String,
+        /// Repository id, path or name, e.g. owner/project for the root repository
+        repository: String,
+        /// Fetch digest, size and creation time of every tag, one request per tag
+        #[arg(long, default_value_t = false)]
+        details: bool,
+    },
+    /// Delete tags of a container registry repository
+    RegistryTagDelete {
+        /// Full path to the project, for example: owner/project
+        project: String,
+        /// Repository id, path or name, e.g. owner/project for the root repository
+        repository: String,
+        #[arg(required = true)]
+        tags: Vec<String>,
+    },
+    /// Delete tags of a container registry repository in bulk. `latest` is never deleted
+    RegistryPrune {
+        /// Full path to the project, for example: owner/project
+        project: String,
+        /// Repository id, path or name, e.g. owner/project for the root repository
+        repository: String,
+        /// Delete tags whose whole name matches this regex, `.*` for all tags
+        #[arg(long)]
+        delete_regex: String,
+        /// Keep tags whose whole name matches this regex
+        #[arg(long)]
+        keep_regex: Option<String>,
+        /// Only delete tags older than this, e.g. 7d, 2weeks or 1month
+        #[arg(long)]
+        older_than: Option<String>,
+        /// Keep the newest n tags that would otherwise be deleted
+        #[arg(long)]
+        keep_n: Option<u32>,
+        /// List the tags that would be deleted without deleting them
+        #[arg(long, default_value_t = false)]
+        dry_run: bool,
+    },
+    /// Show or change the cleanup policy of the container registry of a project
+    RegistryPolicy {
+        /// Full path to the project, for example: owner/project
+        project: String,
+        #[command(subcommand)]
+        action: RegistryPolicyAction,
+    },
+    /// Push an OCI image layout (e.g. from podman push --format oci <image> oci:<dir>)
+    /// to a container registry, without podman or docker
+    RegistryPush {
+        /// Directory of the OCI image layout
+        layout: PathBuf,
+        /// Image to push to, e.g. registry.gitlab.com/owner/project:tag.
+        /// Prefix it with http:// for a registry without TLS
+        image: String,
+        /// More tags to push the image under
+        #[arg(short = 't', long = "tag")]
+        tags: Vec<String>,
+        /// Push the entry of the layout saved under this name instead of all entries
+        #[arg(long)]
+        ref_name: Option<String>,
+    },
+    /// List the releases of a project, newest first
+    ReleaseList {
+        /// Full path to the project, for example: owner/project
+        project: String,
+    },
+    /// Show the release of a tag
+    ReleaseShow {
+        /// Full path to the project, for example: owner/project
+        project: String,
+        /// Tag of the release, e.g. v0.1.0
+        tag: String,
+    },
+    /// Show the latest release of a project
+    ReleaseLatest {
+        /// Full path to the project, for example: owner/project
+        project: String,
+    },
+    /// Create a release for a tag
+    ReleaseCreate {
+        /// Full path to the project, for example: owner/project
+        project: String,
+        /// Tag of the release, e.g. v0.1.0
+        tag: String,
+        /// Title of the release, defaults to the tag
+        #[arg(long)]
+        name: Option<String>,
+        /// File with the release notes in markdown, - reads them from stdin
+        #[arg(short = 'F', long)]
+        notes_file: Option<PathBuf>,
+        /// Commit, branch or tag to create the tag from if it does not exist
+        #[arg(long = "ref")]
+        git_ref: Option<String>,
+        /// Title of a milestone to associate, can be repeated
+        #[arg(long = "milestone")]
+        milestones: Vec<String>,
+        /// ISO 8601 date of the release, e.g. 2025-03-01T10:00:00Z; a future date makes an upcoming release
+        #[arg(long)]
+        released_at: Option<String>,
+        /// Link every file of the generic package name@version as a release asset, can be repeated
+        #[arg(long = "assets-from-package")]
+        assets_from_packages: Vec<String>,
+    },
+    /// Resolve, lock and download the packages listed in a glabu.toml/glabu.yaml manifest
+    PackageSync {
+        /// Path to the manifest, defaults to glabu.toml or glabu.yaml in the current directory
+        #[arg(short = 'm', long)]
+        manifest: Option<PathBuf>,
+        /// Path to the lockfile, defaults to glabu.lock next to the manifest
+        #[arg(long)]
+        lockfile: Option<PathBuf>,
+        /// Directory to download into, overrides target_dir of the manifest
+        #[arg(short = 'o', long)]
+        target_dir: Option<PathBuf>,
+        /// Fail if the lockfile is missing or out of date instead of updating it
+        #[arg(long, default_value_t = false)]
+        locked: bool,
+        /// Resolve all packages again, ignoring the lockfile
+        #[arg(short = 'u', long, default_value_t = false)]
+        update: bool,
+    },
+    /// Install the binary for the current architecture from a generic package
+    Install {
+        /// Full path to the project, for example: owner/project
+        project: String,
+        /// Name of the package
+        package_name: String,
+        /// Version of the package
+        #[arg(short = 'v', long)]
+        package_version: Option<String>,
+        /// How to pick the version when none is given: created-at, semver-max,
+        /// or a semver requirement such as ^1.4 or ~2.0
+        #[arg(short = 's', long, default_value_t = VersionStrategy::CreatedAt)]
+        strategy: VersionStrategy,
+        /// Filename regex, for when several files match the current architecture
+        #[arg(short = 'r', long)]
+        regex: Option<String>,
+        /// Directory to install into, defaults to $HOME/.local/bin
+        #[arg(short = 'b', long)]
+        bin_dir: Option<PathBuf>,
+        /// Name of the installed binary, defaults to the package name
+        #[arg(short = 'm', long)]
+        binary_name: Option<String>,
     },
+    /// Replace this glabu binary with the latest one from puterize/prebuilt
+    SelfUpdate,
     /// Generates shell completion scripts
     Completions {
         /// The shell to generate completions for
@@ -132,17 +705,56 @@ pub async fn execute() -> Result<(), Box<dyn std::error::Error>> {
             package_name,
             package_version,
             latest,
+            strategy,
+            package_type,
             package_file,
             regex,
-            output_dir,
+            output,
+            output_template,
+            no_clobber,
+            extract,
+            strip_components,
+            public_key,
+            require_signature,
         } => {
-            let project = encode_project_id(&project);
-            let mut pf = GenericPackageOp::new(&project, &package_name, "");
-            pf.package_version = package_version;
-            if latest {
-                pf.package_version = None;
-            }
-            pf.download_files(output_dir, regex, package_file).await?;
+            let public_key = public_key
+                .or_else(|| std::env::var(signing::PUBLIC_KEY_ENV).ok())
+                .map(|key| signing::read_public_key(&key))
+                .transpose()?;
+            let (pf, package_file, regex) = if is_url(&project) {
+                if package_name.is_some() || package_version.is_some() || latest {
+                    return Err(
+                        "A package url cannot be combined with a package name or version".into(),
+                    );
+                }
+                let pf = GenericPackageOp::from_url(&project).await?;
+                // without filters, a file url selects its file and a package url all files
+                let (package_file, regex) = match (package_file, regex) {
+                    (None, None) if pf.file_name.is_empty() => (None, Some(".*".to_string())),
+                    (None, None) => (Some(pf.file_name.clone()), None),
+                    filters => filters,
+                };
+                (pf, package_file, regex)
+            } else {
+                let package_name = package_name.ok_or("--package-name is required")?;
+                let project = encode_project_id(&project);
+                let mut pf = GenericPackageOp::new(&project, &package_name, "");
+                pf.package_version = package_version;
+                if latest {
+                    pf.package_version = None;
+                }
+                (pf, package_file, regex)
+            };
+            let pf = pf
+                .version_strategy(strategy)
+                .package_type(package_type)
+                .output_template(output_template)
+                .no_clobber(no_clobber)
+                .extract(extract)
+                .strip_components(strip_components)
+                .public_key(public_key)
+                .require_signature(require_signature);
+            pf.download_files(output, regex, package_file).await?;
         }
         Commands::PackageUpload {
             project,
@@ -150,22 +762,66 @@ pub async fn execute() -> Result<(), Box<dyn std::error::Error>> {
             package_version,
             file_path,
             file_name,
+            sha256sums,
+            status,
+            sign_key,
         } => {
             let project = encode_project_id(&project);
-            let generic_package_op = GenericPackageOp::new(&project, &package_name, "");
-            let file_path: PathBuf = PathBuf::from(&file_path);
-            if !file_path.exists() {
-                return Err(format!("File not found: {}", &file_path.display()).into());
+            let generic_package_op = GenericPackageOp::new(&project, &package_name, "")
+                .signing_key(read_signing_key(sign_key)?)
+                .status(status);
+            if file_name.is_some() && file_path.len() > 1 {
+                return Err("--file-name can only be used when uploading a single file".into());
+            }
+            let file_paths: Vec<PathBuf> = file_path.iter().map(PathBuf::from).collect();
+            if let Some(missing) = file_paths.iter().find(|path| !path.exists()) {
+                return Err(format!("File not found: {}", missing.display()).into());
+            }
+            for file_path in file_paths {
+                let file_name = file_name.clone().unwrap_or_else(|| {
+                    file_path
+                        .file_name()
+                        .map(|s| s.to_string_lossy().to_string())
+                        .ok_or("File name not found")
+                        .unwrap()
+                });
+                generic_package_op
+                    .upload_package_file(&package_version, &file_name, file_path)
+                    .await?;
+            }
+            if sha256sums {
+                generic_package_op
+                    .upload_sha256sums(&package_version)
+                    .await?;
             }
-            let file_name = file_name.unwrap_or_else(|| {
-                file_path
-                    .file_name()
-                    .map(|s| s.to_string_lossy().to_string())
-                    .ok_or("File name not found")
-                    .unwrap()
-            });
-            generic_package_op
-                .upload_package_file(&package_version, &file_name, file_path)
+        }
+        Commands::PackagePublish {
+            project,
+            package_name,
+            package_version,
+            file_path,
+            sha256sums,
+            sign_key,
+        } => {
+            let project = encode_project_id(&project);
+            GenericPackageOp::new(&project, &package_name, "")
+                .signing_key(read_signing_key(sign_key)?)
+                .publish(&package_version, file_path, sha256sums)
+                .await?;
+        }
+        Commands::PackageVerify {
+            project,
+            package_name,
+            package_version,
+            strategy,
+            dir,
+        } => {
+            let project = encode_project_id(&project);
+            PackageVerifyOp::new(&project, &package_name)
+                .package_version(package_version)
+                .version_strategy(strategy)
+                .local_dir(dir)
+                .run()
                 .await?;
         }
         Commands::ProjectCreate {
@@ -198,19 +854,434 @@ pub async fn execute() -> Result<(), Box<dyn std::error::Error>> {
             let res_json = serde_json::to_string_pretty(&res)?;
             println!("{}", res_json);
         }
+        Commands::PackageList {
+            project,
+            package_name,
+            package_version,
+            package_type,
+            status,
+        } => {
+            let project = encode_project_id(&project);
+            let packages = ProjectPackageListOp::new(&project)
+                .package_name(package_name)
+                .package_version(package_version)
+                .package_type(package_type)
+                .status(status)
+                .list_all()
+                .await?;
+            println!("{}", serde_json::to_string_pretty(&packages)?);
+        }
         Commands::PackageFileList {
             project,
             package_name,
             package_version,
+            package_type,
         } => {
             let project = encode_project_id(&project);
             let package_list_op = ProjectPackageListOp::new(&project)
                 .package_name(Some(package_name.as_str().into()))
-                .package_version(Some(package_version.as_str().into()));
+                .package_version(Some(package_version.as_str().into()))
+                .package_type(package_type);
             let files = package_list_op.list().await?;
             let files_json = serde_json::to_string_pretty(&files)?;
             println!("{}", files_json);
         }
+        Commands::PackageFind {
+            package_name,
+            group,
+            package_type,
+            strategy,
+        } => {
+            let matches =
+                packages_find(&package_name, group.as_deref(), package_type, &strategy).await?;
+            let matches_json = serde_json::to_string_pretty(&matches)?;
+            println!("{}", matches_json);
+        }
+        Commands::PackageCopy {
+            from_project,
+            to_project,
+            package_name,
+            package_version,
+            strategy,
+            to_version,
+            to_host,
+            to_token_env,
+        } => {
+            let to_instance = to_host
+                .map(|host| GitlabInstance::from_env(&host, &to_token_env))
+                .transpose()?;
+            PackageCopyOp::new(&from_project, &to_project, &package_name)
+                .package_version(package_version)
+                .version_strategy(strategy)
+                .to_version(to_version)
+                .to_instance(to_instance)
+                .run()
+                .await?;
+        }
+        Commands::PackageUsage {
+            path,
+            group,
+            package_type,
+            top,
+            growth,
+            by_name,
+            format,
+        } => {
+            let path = encode_project_id(&path);
+            PackageUsageOp::new(&path, group)
+                .package_type(package_type)
+                .top(top)
+                .growth(growth)
+                .by_name(by_name)
+                .format(format)
+                .run()
+                .await?;
+        }
+        Commands::PypiUpload { project, files } => {
+            let project = encode_project_id(&project);
+            let pypi_op = PypiOp::new(&project);
+            for file in files {
+                pypi_op.upload(&file).await?;
+            }
+        }
+        Commands::PypiDownload {
+            project,
+            package_name,
+            package_version,
+            strategy,
+            regex,
+            output,
+        } => {
+            let project = encode_project_id(&project);
+            PypiOp::new(&project)
+                .download(
+                    &package_name,
+                    package_version,
+                    &strategy,
+                    regex.as_deref(),
+                    &output,
+                )
+                .await?;
+        }
+        Commands::NpmPublish {
+            project,
+            tarball,
+            tag,
+        } => {
+            let project = encode_project_id(&project);
+            NpmOp::new(&project).publish(&tarball, &tag).await?;
+        }
+        Commands::NpmDistTag { project, action } => {
+            let project = encode_project_id(&project);
+            let npm_op = NpmOp::new(&project);
+            let package_name = match action {
+                DistTagAction::Add { package_spec, tag } => {
+                    let (package_name, version) = split_package_spec(&package_spec)?;
+                    npm_op.add_dist_tag(package_name, version, &tag).await?;
+                    package_name.to_string()
+                }
+                DistTagAction::Rm { package_name, tag } => {
+                    npm_op.remove_dist_tag(&package_name, &tag).await?;
+                    package_name
+                }
+                DistTagAction::Ls { package_name } => package_name,
+            };
+            let tags = npm_op.dist_tags(&package_name).await?;
+            let msg = PrintOutput {
+                status: "ok".to_string(),
+                output: tags,
+            };
+            println!("{}", serde_json::to_string_pretty(&msg)?);
+        }
+        Commands::HelmPush {
+            project,
+            chart,
+            channel,
+        } => {
+            let project = encode_project_id(&project);
+            HelmOp::new(&project, &channel).push(&chart).await?;
+        }
+        Commands::HelmIndex { project, channel } => {
+            let project = encode_project_id(&project);
+            let msg = PrintOutput {
+                status: "ok".to_string(),
+                output: HelmOp::new(&project, &channel).index().await?,
+            };
+            println!("{}", serde_json::to_string_pretty(&msg)?);
+        }
+        Commands::HelmPull {
+            project,
+            chart_name,
+            chart_version,
+            strategy,
+            channel,
+            output,
+        } => {
+            let project = encode_project_id(&project);
+            HelmOp::new(&project, &channel)
+                .pull(&chart_name, chart_version.as_deref(), &strategy, output)
+                .await?;
+        }
+        Commands::TerraformModulePublish {
+            project,
+            dir,
+            name,
+            system,
+            version,
+        } => {
+            let project = encode_project_id(&project);
+            TerraformModuleOp::new(&project)
+                .publish(&dir, &name, &system, &version)
+                .await?;
+        }
+        Commands::TerraformModuleList { project, name } => {
+            let project = encode_project_id(&project);
+            let modules = TerraformModuleOp::new(&project).list(name).await?;
+            println!("{}", serde_json::to_string_pretty(&modules)?);
+        }
+        Commands::DebianUpload {
+            project,
+            files,
+            distribution,
+            component,
+            create_distribution,
+        } => {
+            let project = encode_project_id(&project);
+            DebianOp::new(&project)
+                .distribution(distribution)
+                .component(&component)
+                .create_distribution(create_distribution)
+                .upload(&files)
+                .await?;
+        }
+        Commands::RpmUpload { project, files } => {
+            let project = encode_project_id(&project);
+            RpmOp::new(&project).upload_all(&files).await?;
+        }
+        Commands::RegistryRepos { project } => {
+            let project = encode_project_id(&project);
+            let repositories = RegistryOp::new(&project).repositories().await?;
+            println!("{}", serde_json::to_string_pretty(&repositories)?);
+        }
+        Commands::RegistryTags {
+            project,
+            repository,
+            details,
+        } => {
+            let project = encode_project_id(&project);
+            let registry_op = RegistryOp::new(&project);
+            let repository = registry_op.repository(&repository).await?;
+            let tags = if details {
+                registry_op.tags_with_details(repository.id).await?
+            } else {
+                registry_op.tags(repository.id).await?
+            };
+            println!("{}", serde_json::to_string_pretty(&tags)?);
+        }
+        Commands::RegistryTagDelete {
+            project,
+            repository,
+            tags,
+        } => {
+            let project = encode_project_id(&project);
+            let registry_op = RegistryOp::new(&project);
+            let repository = registry_op.repository(&repository).await?;
+            for tag in &tags {
+                registry_op.delete_tag(repository.id, tag).await?;
+                eprintln!("Deleted {}:{}", &repository.path, tag);
+            }
+            let msg = PrintOutput {
+                status: "ok".to_string(),
+                output: tags,
+            };
+            println!("{}", serde_json::to_string_pretty(&msg)?);
+        }
+        Commands::RegistryPrune {
+            project,
+            repository,
+            delete_regex,
+            keep_regex,
+            older_than,
+            keep_n,
+            dry_run,
+        } => {
+            let project = encode_project_id(&project);
+            let rules = PruneRules {
+                name_regex_delete: delete_regex,
+                name_regex_keep: keep_regex,
+                keep_n,
+                older_than,
+            };
+            RegistryOp::new(&project)
+                .prune(&repository, rules, dry_run)
+                .await?;
+        }
+        Commands::RegistryPolicy { project, action } => {
+            let project = encode_project_id(&project);
+            let registry_op = RegistryOp::new(&project);
+            let policy = match action {
+                RegistryPolicyAction::Show => registry_op.policy().await?,
+                RegistryPolicyAction::Set {
+                    enabled,
+                    cadence,
+                    keep_n,
+                    older_than,
+                    name_regex,
+                    name_regex_keep,
+                } => {
+                    let attributes = ContainerExpirationPolicyAttributes {
+                        cadence,
+                        enabled,
+                        keep_n,
+                        older_than,
+                        name_regex,
+                        name_regex_keep,
+                    };
+                    registry_op.set_policy(&attributes).await?
+                }
+            };
+            let msg = PrintOutput {
+                status: "ok".to_string(),
+                output: policy,
+            };
+            println!("{}", serde_json::to_string_pretty(&msg)?);
+        }
+        Commands::RegistryPush {
+            layout,
+            image,
+            tags,
+            ref_name,
+        } => {
+            let image = ImageReference::parse(&image)?;
+            let layout = OciLayout::open(&layout)?;
+            let root = layout.root(ref_name.as_deref())?;
+            let mut tags: Vec<String> = image.tag.into_iter().chain(tags).collect();
+            if let (true, Root::Descriptor(descriptor)) = (tags.is_empty(), &root) {
+                tags.extend(descriptor.ref_name().map(String::from));
+            }
+            let mut client = RegistryClient::new(httpclient(), &image.registry, &image.repository)
+                .credentials("glabu", gitlab_token());
+            client.authenticate().await?;
+            let msg = PrintOutput {
+                status: "ok".to_string(),
+                output: oci::push_layout(&layout, &client, root, &tags).await?,
+            };
+            println!("{}", serde_json::to_string_pretty(&msg)?);
+        }
+        Commands::ReleaseList { project } => {
+            let project = encode_project_id(&project);
+            let releases = ProjectReleasesGet::for_project(&project).run().await?;
+            println!("{}", serde_json::to_string_pretty(&releases)?);
+        }
+        Commands::ReleaseShow { project, tag } => {
+            let project = encode_project_id(&project);
+            let release = ProjectReleasesGet::for_project(&project)
+                .get_by_tag(&tag)
+                .await?;
+            println!("{}", serde_json::to_string_pretty(&release)?);
+        }
+        Commands::ReleaseLatest { project } => {
+            let project = encode_project_id(&project);
+            let release = ProjectReleasesGet::for_project(&project).latest().await?;
+            println!("{}", serde_json::to_string_pretty(&release)?);
+        }
+        Commands::ReleaseCreate {
+            project,
+            tag,
+            name,
+            notes_file,
+            git_ref,
+            milestones,
+            released_at,
+            assets_from_packages,
+        } => {
+            let notes = match notes_file {
+                Some(path) if path.as_os_str() == "-" => {
+                    let mut notes = String::new();
+                    std::io::stdin().read_to_string(&mut notes)?;
+                    Some(notes)
+                }
+                Some(path) => Some(std::fs::read_to_string(path)?),
+                None => None,
+            };
+            let project = encode_project_id(&project);
+            let mut create = ReleaseCreate::new(&project, &tag)
+                .name(name)
+                .description(notes)
+                .git_ref(git_ref)
+                .milestones(milestones)
+                .released_at(released_at);
+            for package_spec in assets_from_packages {
+                let (package_name, version) = split_package_spec(&package_spec)?;
+                create = create.assets_from_package(package_name, version).await?;
+            }
+            let msg = PrintOutput {
+                status: "ok".to_string(),
+                output: create.run().await?,
+            };
+            println!("{}", serde_json::to_string_pretty(&msg)?);
+        }
+        Commands::PackageSync {
+            manifest,
+            lockfile,
+            target_dir,
+            locked,
+            update,
+        } => {
+            PackageSyncOp::new(manifest)?
+                .lockfile_path(lockfile)
+                .target_dir(target_dir)
+                .locked(locked)
+                .update(update)
+                .run()
+                .await?;
+        }
+        Commands::PackageWatch {
+            project,
+            package_name,
+            strategy,
+            regex,
+            output,
+            hook,
+            since,
+            interval,
+            max_interval,
+        } => {
+            let project = encode_project_id(&project);
+            PackageWatchOp::new(&project, &package_name, output)
+                .version_strategy(strategy)
+                .pattern(regex)
+                .hook(hook)
+                .since(since)
+                .interval(Duration::from_secs(interval))
+                .max_interval(Duration::from_secs(max_interval))
+                .run()
+                .await?;
+        }
+        Commands::Install {
+            project,
+            package_name,
+            package_version,
+            strategy,
+            regex,
+            bin_dir,
+            binary_name,
+        } => {
+            let bin_dir = match bin_dir {
+                Some(bin_dir) => bin_dir,
+                None => PathBuf::from(std::env::var("HOME")?).join(".local/bin"),
+            };
+            let binary_name = binary_name.unwrap_or_else(|| package_name.clone());
+            BinaryInstallOp::new(&project, &package_name, &bin_dir, &binary_name)
+                .package_version(package_version)
+                .version_strategy(strategy)
+                .pattern(regex)
+                .run()
+                .await?;
+        }
+        Commands::SelfUpdate => {
+            BinaryInstallOp::self_update()?.run().await?;
+        }
         Commands::ProjectForkPrivate {
             project_url,
             targe_name,
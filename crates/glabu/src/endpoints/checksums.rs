@@ -0,0 +1,316 @@
+//! `SHA256SUMS` manifests for generic package versions.
+//!
+//! The manifest uses the `sha256sum` format (`<hex>  <file name>` per line), so consumers
+//! can check downloads with `sha256sum -c SHA256SUMS`. [`PackageVerifyOp`] compares it with
+//! the sha256 GitLab reports for every file and, optionally, with local copies.
+
+use super::packages::{
+    GenericPackageOp, ProjectPackageListOp, VersionStrategy, download_bytes, file_sha256,
+    generic_package_file_url, latest_uploads,
+};
+use crate::endpoints::PrintOutput;
+use crate::signing::SIGNATURE_SUFFIX;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+pub const SHA256SUMS: &str = "SHA256SUMS";
+
+/// Whether a package file is the checksum manifest itself or its signature.
+pub fn is_sha256sums_file(file_name: &str) -> bool {
+    file_name == SHA256SUMS
+        || file_name
+            .strip_prefix(SHA256SUMS)
+            .is_some_and(|rest| rest == SIGNATURE_SUFFIX)
+}
+
+/// Checks downloaded content against the sha256 the registry reports for it, if any,
+/// and returns the sha256 of the content.
+pub fn verify_sha256(
+    file_name: &str,
+    content: &[u8],
+    expected: Option<&str>,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let sha256 = hex::encode(Sha256::digest(content));
+    match expected {
+        Some(expected) if !expected.eq_ignore_ascii_case(&sha256) => Err(format!(
+            "ChecksumMismatch: {} has sha256 {}, expected {}",
+            file_name, sha256, expected
+        )
+        .into()),
+        _ => Ok(sha256),
+    }
+}
+
+/// Formats file name -> sha256 pairs as a `SHA256SUMS` file.
+pub fn format_sha256sums(sums: &BTreeMap<String, String>) -> String {
+    sums.iter()
+        .map(|(file_name, sha256)| format!("{}  {}\n", sha256, file_name))
+        .collect()
+}
+
+/// Parses a `SHA256SUMS` file into file name -> sha256 pairs.
+/// Both text (`<hex>  name`) and binary (`<hex> *name`) lines are accepted.
+pub fn parse_sha256sums(content: &str) -> Result<BTreeMap<String, String>, String> {
+    let mut sums = BTreeMap::new();
+    for (n, line) in content.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let invalid = || format!("InvalidChecksums: line {}: {}", n + 1, line);
+        let (sha256, file_name) = line.split_once(char::is_whitespace).ok_or_else(invalid)?;
+        let file_name = file_name.trim_start();
+        let file_name = file_name.strip_prefix('*').unwrap_or(file_name);
+        if sha256.len() != 64 || !sha256.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(invalid());
+        }
+        if file_name.is_empty() {
+            return Err(invalid());
+        }
+        sums.insert(file_name.to_string(), sha256.to_ascii_lowercase());
+    }
+    Ok(sums)
+}
+
+/// A file whose checksum could not be verified.
+#[derive(Debug, Serialize, PartialEq, Eq)]
+pub struct ChecksumProblem {
+    pub file_name: String,
+    pub problem: String,
+}
+
+/// Outcome of comparing a `SHA256SUMS` manifest with the registry and local files.
+#[derive(Debug, Default, Serialize, PartialEq, Eq)]
+pub struct ChecksumReport {
+    /// Files whose checksums all agree
+    pub verified: Vec<String>,
+    /// Listed files that are not in the local directory
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub missing_locally: Vec<String>,
+    pub problems: Vec<ChecksumProblem>,
+}
+
+/// Compares the manifest with the sha256 of the package files reported by GitLab
+/// and, when given, with the sha256 of the local files.
+pub fn check_sha256sums(
+    sums: &BTreeMap<String, String>,
+    registry: &BTreeMap<String, String>,
+    local: Option<&BTreeMap<String, String>>,
+) -> ChecksumReport {
+    let mut report = ChecksumReport::default();
+    let problem = |file_name: &str, problem: String| ChecksumProblem {
+        file_name: file_name.to_string(),
+        problem,
+    };
+    for (file_name, expected) in sums {
+        match registry.get(file_name) {
+            None => {
+                let p = problem(file_name, "not in package".to_string());
+                report.problems.push(p);
+                continue;
+            }
+            Some(actual) if actual != expected => {
+                let p = problem(
+                    file_name,
+                    format!("registry sha256 {} differs from {}", actual, expected),
+                );
+                report.problems.push(p);
+                continue;
+            }
+            Some(_) => {}
+        }
+        match local.map(|local| local.get(file_name)) {
+            Some(None) => report.missing_locally.push(file_name.clone()),
+            Some(Some(actual)) if actual != expected => {
+                let p = problem(
+                    file_name,
+                    format!("local sha256 {} differs from {}", actual, expected),
+                );
+                report.problems.push(p);
+            }
+            _ => report.verified.push(file_name.clone()),
+        }
+    }
+    for file_name in registry.keys().filter(|f| !sums.contains_key(*f)) {
+        let p = problem(file_name, format!("not listed in {}", SHA256SUMS));
+        report.problems.push(p);
+    }
+    report
+}
+
+/// Result of verifying a package version.
+#[derive(Debug, Serialize)]
+pub struct PackageVerifyOutput {
+    pub package_name: String,
+    pub package_version: String,
+    #[serde(flatten)]
+    pub report: ChecksumReport,
+}
+
+#[derive(Debug, Clone)]
+pub struct PackageVerifyOp {
+    ///  Your project ID or URL-encoded path
+    pub project_id: String,
+    pub package_name: String,
+    /// Version to verify, resolved with `version_strategy` when not set
+    pub package_version: Option<String>,
+    pub version_strategy: VersionStrategy,
+    /// Directory with downloaded files to check as well
+    pub local_dir: Option<PathBuf>,
+}
+
+impl PackageVerifyOp {
+    pub fn new(project_id: &str, package_name: &str) -> Self {
+        Self {
+            project_id: project_id.to_string(),
+            package_name: package_name.to_string(),
+            package_version: None,
+            version_strategy: VersionStrategy::default(),
+            local_dir: None,
+        }
+    }
+
+    pub fn package_version(mut self, package_version: Option<String>) -> Self {
+        self.package_version = package_version;
+        self
+    }
+    pub fn version_strategy(mut self, version_strategy: VersionStrategy) -> Self {
+        self.version_strategy = version_strategy;
+        self
+    }
+    pub fn local_dir(mut self, local_dir: Option<PathBuf>) -> Self {
+        self.local_dir = local_dir;
+        self
+    }
+
+    pub async fn run(self) -> Result<(), Box<dyn std::error::Error>> {
+        let package = GenericPackageOp::new(&self.project_id, &self.package_name, "")
+            .package_version(self.package_version.clone())
+            .version_strategy(self.version_strategy.clone())
+            .resolve_package()
+            .await?;
+        let files = latest_uploads(
+            ProjectPackageListOp::new(&self.project_id)
+                .package_files(&package)
+                .await?,
+        );
+        if !files.iter().any(|f| f.file_name == SHA256SUMS) {
+            return Err(format!(
+                "NoChecksums: {}@{} has no {} file",
+                &package.name, &package.version, SHA256SUMS
+            )
+            .into());
+        }
+        let url = generic_package_file_url(
+            &self.project_id,
+            &package.name,
+            &package.version,
+            SHA256SUMS,
+        )?;
+        let sums = parse_sha256sums(&String::from_utf8(download_bytes(url).await?)?)?;
+        let registry = files
+            .into_iter()
+            .filter(|f| !is_sha256sums_file(&f.file_name))
+            .map(|f| {
+                let sha256 = f.file_sha256.unwrap_or_default();
+                (f.file_name, sha256)
+            })
+            .collect();
+        let local = match &self.local_dir {
+            Some(dir) => {
+                let mut local = BTreeMap::new();
+                for file_name in sums.keys() {
+                    let path = dir.join(file_name);
+                    if path.is_file() {
+                        local.insert(file_name.clone(), file_sha256(&path)?);
+                    }
+                }
+                Some(local)
+            }
+            None => None,
+        };
+
+        let report = check_sha256sums(&sums, &registry, local.as_ref());
+        let problems = report.problems.len();
+        let msg = PrintOutput {
+            status: if problems == 0 { "ok" } else { "error" }.to_string(),
+            output: PackageVerifyOutput {
+                package_name: package.name,
+                package_version: package.version,
+                report,
+            },
+        };
+        println!("{}", serde_json::to_string_pretty(&msg)?);
+        if problems > 0 {
+            return Err(format!("ChecksumMismatch: {} files failed verification", problems).into());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod checksums_tests {
+    use super::*;
+
+    fn sums(pairs: &[(&str, &str)]) -> BTreeMap<String, String> {
+        pairs
+            .iter()
+            .map(|(name, sha256)| (name.to_string(), sha256.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_sha256sums_roundtrip() {
+        let a = "a".repeat(64);
+        let b = "B".repeat(64);
+        let content = format!("{}  glabu-x86_64\n{} *glabu-aarch64\n\n", a, b);
+        let parsed = parse_sha256sums(&content).unwrap();
+        let expected = sums(&[("glabu-x86_64", &a), ("glabu-aarch64", &"b".repeat(64))]);
+        assert_eq!(parsed, expected);
+        assert_eq!(
+            parse_sha256sums(&format_sha256sums(&parsed)).unwrap(),
+            parsed
+        );
+        assert!(parse_sha256sums("abc  glabu").is_err());
+        assert!(is_sha256sums_file("SHA256SUMS.minisig"));
+        assert!(!is_sha256sums_file("glabu.minisig"));
+    }
+
+    #[test]
+    fn test_verify_sha256() {
+        let sha256 = "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824";
+        assert_eq!(verify_sha256("a", b"hello", None).unwrap(), sha256);
+        assert_eq!(
+            verify_sha256("a", b"hello", Some(&sha256.to_uppercase())).unwrap(),
+            sha256
+        );
+        let err = verify_sha256("a", b"hello!", Some(sha256)).unwrap_err();
+        assert!(
+            err.to_string()
+                .starts_with("ChecksumMismatch: a has sha256")
+        );
+    }
+
+    #[test]
+    fn test_check_sha256sums() {
+        let (a, b, c) = ("a".repeat(64), "b".repeat(64), "c".repeat(64));
+        let manifest = sums(&[("x", &a), ("y", &b), ("z", &c)]);
+        let registry = sums(&[("x", &a), ("y", &c), ("extra", &a)]);
+        let local = sums(&[("x", &b)]);
+
+        let report = check_sha256sums(&manifest, &registry, None);
+        assert_eq!(report.verified, vec!["x"]);
+        let problems: Vec<_> = report.problems.iter().map(|p| &p.file_name).collect();
+        assert_eq!(problems, vec!["y", "z", "extra"]);
+
+        let report = check_sha256sums(&manifest, &registry, Some(&local));
+        assert!(report.verified.is_empty());
+        assert_eq!(report.problems[0].file_name, "x");
+
+        let report = check_sha256sums(&manifest, &manifest, Some(&sums(&[("x", &a)])));
+        assert_eq!(report.verified, vec!["x"]);
+        assert_eq!(report.missing_locally, vec!["y", "z"]);
+        assert!(report.problems.is_empty());
+    }
+}
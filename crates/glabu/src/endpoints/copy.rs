@@ -0,0 +1,283 @@
+//! Copy (promote) a generic package version to another project, possibly on another instance.
+//!
+//! Every file is streamed from the source to the target without buffering it.
+//! The sha256 of the stream is checked against the one reported by the source registry,
+//! and the sha256 reported by the target is checked as well. Since the file is only
+//! complete once uploaded, a file failing either check is deleted from the target again.
+
+use super::packages::{
+    GenericPackageOp, ProjectPackageListOp, VersionStrategy, generic_package_file_url,
+    latest_uploads,
+};
+use super::setup::{GitlabInstance, gitlab_token, httpclient};
+use crate::endpoints::PrintOutput;
+use futures_util::{Stream, StreamExt};
+use reqwest::Body;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::sync::{Arc, Mutex};
+
+/// A file copied by [`PackageCopyOp`].
+#[derive(Debug, Serialize)]
+pub struct CopiedFile {
+    pub file_name: String,
+    pub sha256: String,
+}
+
+/// Result of copying a package version.
+#[derive(Debug, Serialize)]
+pub struct PackageCopyOutput {
+    pub package_name: String,
+    pub from_version: String,
+    pub to_version: String,
+    pub files: Vec<CopiedFile>,
+}
+
+#[derive(Debug, Clone)]
+pub struct PackageCopyOp {
+    /// Full path to the source project, for example: owner/staging
+    pub from_project: String,
+    /// Full path to the target project, for example: owner/prebuilt
+    pub to_project: String,
+    pub package_name: String,
+    /// Version to copy, resolved with `version_strategy` when not set
+    pub package_version: Option<String>,
+    pub version_strategy: VersionStrategy,
+    /// Version in the target project, same as the source version when not set
+    pub to_version: Option<String>,
+    /// Target instance, the source instance when not set
+    pub to_instance: Option<GitlabInstance>,
+}
+
+impl PackageCopyOp {
+    pub fn new(from_project: &str, to_project: &str, package_name: &str) -> Self {
+        Self {
+            from_project: from_project.to_string(),
+            to_project: to_project.to_string(),
+            package_name: package_name.to_string(),
+            package_version: None,
+            version_strategy: VersionStrategy::default(),
+            to_version: None,
+            to_instance: None,
+        }
+    }
+
+    pub fn package_version(mut self, package_version: Option<String>) -> Self {
+        self.package_version = package_version;
+        self
+    }
+    pub fn version_strategy(mut self, version_strategy: VersionStrategy) -> Self {
+        self.version_strategy = version_strategy;
+        self
+    }
+    pub fn to_version(mut self, to_version: Option<String>) -> Self {
+        self.to_version = to_version;
+        self
+    }
+    pub fn to_instance(mut self, to_instance: Option<GitlabInstance>) -> Self {
+        self.to_instance = to_instance;
+        self
+    }
+
+    pub async fn run(self) -> Result<(), Box<dyn std::error::Error>> {
+        let from_project_id = urlencoding::encode(&self.from_project).to_string();
+        let mut source = GenericPackageOp::new(&from_project_id, &self.package_name, "")
+            .version_strategy(self.version_strategy.clone());
+        source.package_version = self.package_version.clone();
+        let package = source.resolve_package().await?;
+        let list_op = ProjectPackageListOp::new(&from_project_id);
+        let to_version = self
+            .to_version
+            .clone()
+            .unwrap_or_else(|| package.version.clone());
+        let upload_op = GenericPackageOp::new(
+            urlencoding::encode(&self.to_project),
+            &self.package_name,
+            "",
+        )
+        .instance(self.to_instance.clone());
+
+        let mut copied = vec![];
+        for file in latest_uploads(list_op.package_files(&package).await?) {
+            let expected = file
+                .file_sha256
+                .clone()
+                .ok_or(format!("No sha256 reported for {}", &file.file_name))?;
+            let url = generic_package_file_url(
+                &from_project_id,
+                &package.name,
+                &package.version,
+                &file.file_name,
+            )?;
+            let response = httpclient()
+                .get(url)
+                .header("Private-Token", gitlab_token())
+                .send()
+                .await?;
+            let status = response.status();
+            if status != 200 {
+                return Err(format!(
+                    "DownloadFileErr: {} failed with status: {}, and message: {}",
+                    &file.file_name,
+                    status,
+                    response.text().await?
+                )
+                .into());
+            }
+            eprintln!(
+                "Copying {} {}@{} -> {}@{}",
+                &file.file_name,
+                &self.from_project,
+                &package.version,
+                &self.to_project,
+                &to_version
+            );
+            let hasher = Arc::new(Mutex::new(Sha256::new()));
+            let body = Body::wrap_stream(hashed(response.bytes_stream(), hasher.clone()));
+            let uploaded = upload_op
+                .upload_package_body(&to_version, &file.file_name, body)
+                .await?;
+            // the upload has consumed the stream, so the hash covers the whole file
+            let downloaded = hex::encode(hasher.lock().unwrap().clone().finalize());
+            if downloaded != expected {
+                return Err(format!(
+                    "ChecksumMismatch: downloaded {} has sha256 {}, expected {}, {}",
+                    &file.file_name,
+                    downloaded,
+                    expected,
+                    roll_back(&upload_op, &uploaded).await
+                )
+                .into());
+            }
+            let uploaded_sha256 = uploaded["file_sha256"].as_str().unwrap_or_default();
+            if uploaded_sha256 != expected {
+                return Err(format!(
+                    "ChecksumMismatch: uploaded {} has sha256 {}, expected {}, {}",
+                    &file.file_name,
+                    uploaded_sha256,
+                    expected,
+                    roll_back(&upload_op, &uploaded).await
+                )
+                .into());
+            }
+            copied.push(CopiedFile {
+                file_name: file.file_name,
+                sha256: expected,
+            });
+        }
+
+        let msg = PrintOutput {
+            status: "ok".to_string(),
+            output: PackageCopyOutput {
+                package_name: package.name,
+                from_version: package.version,
+                to_version,
+                files: copied,
+            },
+        };
+        println!("{}", serde_json::to_string_pretty(&msg)?);
+        Ok(())
+    }
+}
+
+/// Deletes an uploaded file that failed verification, describing the outcome for the error.
+async fn roll_back(upload_op: &GenericPackageOp, uploaded: &serde_json::Value) -> String {
+    match upload_op.delete_uploaded_file(uploaded).await {
+        Ok(()) => "the upload was rolled back".to_string(),
+        Err(e) => format!("rolling back the upload failed, delete it by hand: {}", e),
+    }
+}
+
+/// Passes the chunks of a download through, adding them to `hasher` on the way.
+fn hashed<S, T, E>(stream: S, hasher: Arc<Mutex<Sha256>>) -> impl Stream<Item = Result<T, E>>
+where
+    S: Stream<Item = Result<T, E>>,
+    T: AsRef<[u8]>,
+{
+    stream.inspect(move |chunk| {
+        if let Ok(chunk) = chunk {
+            hasher.lock().unwrap().update(chunk.as_ref());
+        }
+    })
+}
+
+#[cfg(test)]
+mod copy_tests {
+    use super::super::test_gitlab;
+    use super::*;
+
+    #[tokio::test]
+    async fn test_hashed() {
+        let chunks = vec![Ok::<_, ()>(b"glabu ".to_vec()), Ok(b"package".to_vec())];
+        let hasher = Arc::new(Mutex::new(Sha256::new()));
+        let passed: Vec<_> = hashed(futures_util::stream::iter(chunks), hasher.clone())
+            .collect()
+            .await;
+        assert_eq!(passed.len(), 2);
+        assert_eq!(
+            hasher.lock().unwrap().clone().finalize(),
+            Sha256::digest(b"glabu package")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_copy_mismatch_rolls_back() {
+        let sha256 = hex::encode(Sha256::digest(b"glabu binary"));
+        test_gitlab::respond(
+            "GET",
+            "/projects/copy%2Fstaging/packages",
+            200,
+            r#"[{"id": 11, "name": "glabu", "version": "1.0.0", "tags": [],
+                 "created_at": "2025-03-01T10:00:00.000Z", "package_type": "generic"}]"#,
+        );
+        test_gitlab::respond(
+            "GET",
+            "/projects/copy%2Fstaging/packages/11/package_files",
+            200,
+            format!(
+                r#"[{{"id": 21, "package_id": 11, "created_at": "2025-03-01T10:00:00.000Z",
+                     "file_name": "glabu-x86_64", "file_sha256": "{}"}}]"#,
+                sha256
+            ),
+        );
+        // the download is cut short
+        test_gitlab::respond(
+            "GET",
+            "/projects/copy%2Fstaging/packages/generic/glabu/1.0.0/glabu-x86_64",
+            200,
+            "glabu bin",
+        );
+        test_gitlab::respond(
+            "PUT",
+            "/projects/copy%2Fprebuilt/packages/generic/glabu/1.0.0/glabu-x86_64",
+            201,
+            r#"{"id": 31, "package_id": 41, "file_name": "glabu-x86_64"}"#,
+        );
+        test_gitlab::respond(
+            "DELETE",
+            "/projects/copy%2Fprebuilt/packages/41/package_files/31",
+            204,
+            "",
+        );
+
+        let err = PackageCopyOp::new("copy/staging", "copy/prebuilt", "glabu")
+            .package_version(Some("1.0.0".to_string()))
+            .run()
+            .await
+            .unwrap_err()
+            .to_string();
+        assert!(err.starts_with("ChecksumMismatch: downloaded glabu-x86_64"));
+        assert!(err.ends_with("the upload was rolled back"));
+        let target: Vec<_> = test_gitlab::requests("/projects/copy%2Fprebuilt")
+            .into_iter()
+            .map(|r| (r.method, r.body))
+            .collect();
+        assert_eq!(
+            target,
+            vec![
+                ("PUT".to_string(), b"glabu bin".to_vec()),
+                ("DELETE".to_string(), vec![]),
+            ]
+        );
+    }
+}
@@ -0,0 +1,477 @@
+//! Upload to the Debian registry of a GitLab project.
+//!
+//! A `.deb` is published straight into a distribution and component. Without a
+//! distribution, files wait in the incoming package until the `.changes` file that
+//! lists them is uploaded, which is how `dput` publishes a source or binary upload.
+//! See gitlab api doc: https://docs.gitlab.com/api/packages/debian/
+
+use super::setup::{
+    gitlab_api_url, gitlab_api_url_with_query, gitlab_token, httpclient, with_basic_auth,
+};
+use crate::archive;
+use crate::endpoints::PrintOutput;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+
+/// Parses the first paragraph of a deb822 file (`control`, `.changes`) into
+/// lowercased field names and values, continuation lines joined with `\n`.
+/// A clearsigned file is unwrapped first.
+pub fn parse_fields(content: &str) -> BTreeMap<String, String> {
+    let mut lines = content.lines().peekable();
+    if lines
+        .peek()
+        .is_some_and(|l| l.starts_with("-----BEGIN PGP SIGNED MESSAGE"))
+    {
+        // skip the armor headers, they end with an empty line
+        lines.by_ref().take_while(|l| !l.trim().is_empty()).count();
+    }
+    let mut fields: BTreeMap<String, String> = BTreeMap::new();
+    let mut last: Option<String> = None;
+    for line in lines {
+        if line.trim().is_empty() || line.starts_with("-----BEGIN PGP SIGNATURE") {
+            if fields.is_empty() {
+                continue;
+            }
+            break;
+        }
+        if line.starts_with([' ', '\t']) {
+            if let Some(value) = last.as_ref().and_then(|key| fields.get_mut(key)) {
+                if !value.is_empty() {
+                    value.push('\n');
+                }
+                value.push_str(line.trim());
+            }
+            continue;
+        }
+        if let Some((key, value)) = line.split_once(':') {
+            let key = key.trim().to_lowercase();
+            fields.insert(key.clone(), value.trim().to_string());
+            last = Some(key);
+        }
+    }
+    fields
+}
+
+/// Fields of the `control` file of a binary package.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DebianControl {
+    pub package: String,
+    pub version: String,
+    pub architecture: String,
+}
+
+impl DebianControl {
+    pub fn parse(content: &str) -> Result<Self, String> {
+        let mut fields = parse_fields(content);
+        let mut take = |key: &str| {
+            fields
+                .remove(key)
+                .ok_or(format!("InvalidControl: {} is missing", key))
+        };
+        Ok(Self {
+            package: take("package")?,
+            version: take("version")?,
+            architecture: take("architecture")?,
+        })
+    }
+
+    /// Reads the control file from the `control.tar.*` member of a `.deb`.
+    pub fn from_deb(content: &[u8]) -> Result<Self, Box<dyn std::error::Error>> {
+        let (member, data) = ar_members(content)?
+            .into_iter()
+            .find(|(name, _)| name.starts_with("control.tar"))
+            .ok_or("InvalidDeb: no control.tar member")?;
+        let is_control = |path: &Path| path.components().count() <= 2 && path.ends_with("control");
+        let control = match member.as_str() {
+            "control.tar" => archive::read_tar_entry(data, is_control)?,
+            "control.tar.gz" => {
+                archive::read_tar_entry(flate2::read::GzDecoder::new(data), is_control)?
+            }
+            "control.tar.xz" => {
+                archive::read_tar_entry(xz2::read::XzDecoder::new(data), is_control)?
+            }
+            other => {
+                return Err(format!(
+                    "UnsupportedCompression: {}, build the package with xz or gzip",
+                    other
+                )
+                .into());
+            }
+        }
+        .ok_or("InvalidDeb: no control file")?;
+        Ok(Self::parse(&String::from_utf8(control)?)?)
+    }
+}
+
+/// Splits an `ar` archive (the container of a `.deb`) into member names and contents.
+fn ar_members(content: &[u8]) -> Result<Vec<(String, &[u8])>, String> {
+    let mut rest = content
+        .strip_prefix(b"!<arch>\n")
+        .ok_or("InvalidDeb: not an ar archive")?;
+    let mut members = vec![];
+    while rest.len() >= 60 {
+        let (header, body) = rest.split_at(60);
+        let name = String::from_utf8_lossy(&header[..16])
+            .trim_end()
+            .trim_end_matches('/')
+            .to_string();
+        let size: usize = String::from_utf8_lossy(&header[48..58])
+            .trim()
+            .parse()
+            .map_err(|_| format!("InvalidDeb: bad size of member {}", name))?;
+        if body.len() < size {
+            return Err(format!("InvalidDeb: member {} is truncated", name));
+        }
+        members.push((name, &body[..size]));
+        // members are aligned to 2 bytes
+        rest = &body[(size + size % 2).min(body.len())..];
+    }
+    Ok(members)
+}
+
+/// Fields of a `.changes` file that matter for the upload.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ChangesFile {
+    pub source: String,
+    pub version: String,
+    pub distribution: String,
+    /// Names of the files of the upload
+    pub files: Vec<String>,
+}
+
+impl ChangesFile {
+    pub fn parse(content: &str) -> Result<Self, String> {
+        let mut fields = parse_fields(content);
+        let mut take = |key: &str| {
+            fields
+                .remove(key)
+                .ok_or(format!("InvalidChanges: {} is missing", key))
+        };
+        let source = take("source")?;
+        let version = take("version")?;
+        let distribution = take("distribution")?;
+        // each line is `<md5> <size> <section> <priority> <file name>`
+        let files = take("files")?
+            .lines()
+            .filter_map(|line| line.split_whitespace().last())
+            .map(String::from)
+            .collect();
+        Ok(Self {
+            source,
+            version,
+            distribution,
+            files,
+        })
+    }
+}
+
+/// Result of uploading a file.
+#[derive(Debug, Serialize)]
+pub struct DebianUploadOutput {
+    pub file_name: String,
+    pub package_name: String,
+    pub package_version: String,
+    pub architecture: Option<String>,
+    pub distribution: Option<String>,
+    pub sha256: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct DebianOp {
+    ///  Your project ID or URL-encoded path
+    pub project_id: String,
+    /// Codename or suite of the distribution to publish `.deb` files into
+    pub distribution: Option<String>,
+    pub component: String,
+    /// Create missing distributions instead of failing
+    pub create_distribution: bool,
+}
+
+impl DebianOp {
+    pub fn new(project_id: &str) -> Self {
+        Self {
+            project_id: project_id.to_string(),
+            distribution: None,
+            component: "main".to_string(),
+            create_distribution: false,
+        }
+    }
+
+    pub fn distribution(mut self, distribution: Option<String>) -> Self {
+        self.distribution = distribution;
+        self
+    }
+    pub fn component(mut self, component: &str) -> Self {
+        self.component = component.to_string();
+        self
+    }
+    pub fn create_distribution(mut self, create_distribution: bool) -> Self {
+        self.create_distribution = create_distribution;
+        self
+    }
+
+    /// Uploads `.deb` and `.changes` files.
+    ///
+    /// The files listed in a `.changes` are uploaded from its directory before it,
+    /// so passing only the `.changes` is enough.
+    pub async fn upload(&self, paths: &[PathBuf]) -> Result<(), Box<dyn std::error::Error>> {
+        let mut debs = vec![];
+        let mut changes = vec![];
+        for path in paths {
+            let content = std::fs::read(path)?;
+            if path.extension().is_some_and(|ext| ext == "changes") {
+                changes.push((
+                    path.clone(),
+                    ChangesFile::parse(&String::from_utf8(content)?)?,
+                ));
+            } else {
+                debs.push((path.clone(), content));
+            }
+        }
+        let mut listed: BTreeSet<PathBuf> = debs.iter().map(|(path, _)| path.clone()).collect();
+        for (path, changes_file) in &changes {
+            let dir = path.parent().unwrap_or(Path::new("."));
+            for file_name in &changes_file.files {
+                let file = dir.join(file_name);
+                if listed.insert(file.clone()) {
+                    debs.push((file.clone(), std::fs::read(&file)?));
+                }
+            }
+        }
+
+        let mut distributions = BTreeSet::new();
+        if let Some(distribution) = &self.distribution {
+            distributions.insert(distribution.clone());
+        }
+        distributions.extend(changes.iter().map(|(_, c)| c.distribution.clone()));
+        let architectures: BTreeSet<String> = debs
+            .iter()
+            .filter_map(|(_, content)| DebianControl::from_deb(content).ok())
+            .map(|control| control.architecture)
+            .filter(|arch| arch != "all")
+            .collect();
+        for distribution in &distributions {
+            self.ensure_distribution(distribution, &architectures)
+                .await?;
+        }
+
+        let mut outputs = vec![];
+        for (path, content) in debs {
+            let file_name = file_name(&path)?;
+            let output = if file_name.ends_with(".deb") || file_name.ends_with(".udeb") {
+                let control = DebianControl::from_deb(&content)?;
+                DebianUploadOutput {
+                    file_name,
+                    package_name: control.package,
+                    package_version: control.version,
+                    architecture: Some(control.architecture),
+                    distribution: self.distribution.clone(),
+                    sha256: hex::encode(Sha256::digest(&content)),
+                }
+            } else {
+                // sources (.dsc, .tar.*) and build infos of a .changes upload
+                let changes_file = changes
+                    .iter()
+                    .map(|(_, c)| c)
+                    .find(|c| c.files.contains(&file_name))
+                    .ok_or(format!(
+                        "Not a .deb or a file of a .changes: {}",
+                        &file_name
+                    ))?;
+                DebianUploadOutput {
+                    file_name,
+                    package_name: changes_file.source.clone(),
+                    package_version: changes_file.version.clone(),
+                    architecture: None,
+                    distribution: None,
+                    sha256: hex::encode(Sha256::digest(&content)),
+                }
+            };
+            self.upload_file(&output.file_name, content, output.distribution.is_some())
+                .await?;
+            outputs.push(output);
+        }
+        for (path, changes_file) in changes {
+            let file_name = file_name(&path)?;
+            let content = std::fs::read(&path)?;
+            let output = DebianUploadOutput {
+                file_name,
+                package_name: changes_file.source,
+                package_version: changes_file.version,
+                architecture: None,
+                distribution: Some(changes_file.distribution),
+                sha256: hex::encode(Sha256::digest(&content)),
+            };
+            self.upload_file(&output.file_name, content, false).await?;
+            outputs.push(output);
+        }
+        let msg = PrintOutput {
+            status: "ok".to_string(),
+            output: outputs,
+        };
+        println!("{}", serde_json::to_string_pretty(&msg)?);
+        Ok(())
+    }
+
+    async fn upload_file(
+        &self,
+        file_name: &str,
+        content: Vec<u8>,
+        to_distribution: bool,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let path = format!(
+            "/projects/{}/packages/debian/{}",
+            &self.project_id,
+            urlencoding::encode(file_name)
+        );
+        let url = match (to_distribution, &self.distribution) {
+            (true, Some(distribution)) => gitlab_api_url_with_query(
+                &path,
+                &[
+                    ("distribution", distribution.as_str()),
+                    ("component", self.component.as_str()),
+                ],
+            )?,
+            _ => gitlab_api_url(&path)?,
+        };
+        eprintln!("Uploading {}", file_name);
+        let response = with_basic_auth(httpclient().put(url))
+            .body(content)
+            .send()
+            .await?;
+        let status = response.status();
+        if status != 201 {
+            return Err(format!(
+                "Upload of {} failed with status: {}, and message: {}",
+                file_name,
+                status,
+                response.text().await?
+            )
+            .into());
+        }
+        Ok(())
+    }
+
+    /// Checks that the project has the distribution, creating it if allowed.
+    async fn ensure_distribution(
+        &self,
+        codename: &str,
+        architectures: &BTreeSet<String>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let url = gitlab_api_url(&format!(
+            "/projects/{}/debian_distributions/{}",
+            &self.project_id,
+            urlencoding::encode(codename)
+        ))?;
+        let response = httpclient()
+            .get(url)
+            .header("Private-Token", gitlab_token())
+            .send()
+            .await?;
+        let status = response.status();
+        if status.is_success() {
+            return Ok(());
+        }
+        if status != 404 {
+            return Err(format!("Getting distribution failed with status: {}", status).into());
+        }
+        if !self.create_distribution {
+            return Err(format!(
+                "DistributionNotFound: {}, create it in the project or pass --create-distribution",
+                codename
+            )
+            .into());
+        }
+        eprintln!("Creating distribution {}", codename);
+        let mut form = vec![
+            ("codename", codename.to_string()),
+            ("components[]", self.component.clone()),
+        ];
+        form.extend(architectures.iter().map(|a| ("architectures[]", a.clone())));
+        let url = gitlab_api_url(&format!(
+            "/projects/{}/debian_distributions",
+            &self.project_id
+        ))?;
+        let response = httpclient()
+            .post(url)
+            .header("Private-Token", gitlab_token())
+            .form(&form)
+            .send()
+            .await?;
+        let status = response.status();
+        if status != 201 {
+            return Err(format!(
+                "Creating distribution failed with status: {}, and message: {}",
+                status,
+                response.text().await?
+            )
+            .into());
+        }
+        Ok(())
+    }
+}
+
+fn file_name(path: &Path) -> Result<String, Box<dyn std::error::Error>> {
+    Ok(path
+        .file_name()
+        .map(|s| s.to_string_lossy().to_string())
+        .ok_or("File name not found")?)
+}
+
+#[cfg(test)]
+mod debian_tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_control_and_changes() {
+        let control = "Package: glabu\nVersion: 0.1.0-1\nArchitecture: amd64\n\
+                       Description: gitlab cli\n more text\n";
+        let control = DebianControl::parse(control).unwrap();
+        assert_eq!(control.package, "glabu");
+        assert_eq!(control.version, "0.1.0-1");
+        assert_eq!(control.architecture, "amd64");
+        assert!(DebianControl::parse("Package: glabu\n").is_err());
+
+        let changes = "-----BEGIN PGP SIGNED MESSAGE-----\nHash: SHA512\n\n\
+                       Format: 1.8\nSource: glabu\nVersion: 0.1.0-1\nDistribution: bookworm\n\
+                       Files:\n 0123 100 utils optional glabu_0.1.0-1.dsc\n \
+                       4567 200 utils optional glabu_0.1.0-1_amd64.deb\n\n\
+                       -----BEGIN PGP SIGNATURE-----\nabc\n";
+        let changes = ChangesFile::parse(changes).unwrap();
+        assert_eq!(changes.source, "glabu");
+        assert_eq!(changes.distribution, "bookworm");
+        assert_eq!(
+            changes.files,
+            vec!["glabu_0.1.0-1.dsc", "glabu_0.1.0-1_amd64.deb"]
+        );
+    }
+
+    #[test]
+    fn test_ar_members() {
+        let mut ar = b"!<arch>\n".to_vec();
+        for (name, data) in [("debian-binary", &b"2.0\n"[..]), ("control.tar/", b"abc")] {
+            ar.extend(
+                format!(
+                    "{:<16}{:<12}{:<6}{:<6}{:<8}{:<10}`\n",
+                    name,
+                    0,
+                    0,
+                    0,
+                    644,
+                    data.len()
+                )
+                .as_bytes(),
+            );
+            ar.extend(data);
+            if data.len() % 2 == 1 {
+                ar.push(b'\n');
+            }
+        }
+        let members = ar_members(&ar).unwrap();
+        assert_eq!(members[0], ("debian-binary".to_string(), &b"2.0\n"[..]));
+        assert_eq!(members[1], ("control.tar".to_string(), &b"abc"[..]));
+        assert!(ar_members(b"not ar").is_err());
+    }
+}
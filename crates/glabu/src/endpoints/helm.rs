@@ -0,0 +1,196 @@
+//! Push, list and pull charts in the Helm registry of a GitLab project.
+//!
+//! Charts are pushed to and served from a channel, e.g. `stable`.
+//! See gitlab api doc: https://docs.gitlab.com/api/packages/helm/
+
+use super::checksums::verify_sha256;
+use super::packages::{DownloadTarget, PackageDownloadOutput, VersionStrategy, fetch_bytes};
+use super::setup::{gitlab_api_url, httpclient, with_basic_auth};
+use crate::archive::{self, ArchiveKind};
+use crate::endpoints::PrintOutput;
+use crate::models::{ChartVersion, HelmIndex};
+use reqwest::Url;
+use reqwest::multipart::{Form, Part};
+use sha2::{Digest, Sha256};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone)]
+pub struct HelmOp {
+    ///  Your project ID or URL-encoded path
+    pub project_id: String,
+    pub channel: String,
+}
+
+impl HelmOp {
+    pub fn new(project_id: &str, channel: &str) -> Self {
+        Self {
+            project_id: project_id.to_string(),
+            channel: channel.to_string(),
+        }
+    }
+
+    fn channel_path(&self, rest: &str) -> String {
+        format!(
+            "/projects/{}/packages/helm/{}{}",
+            &self.project_id, &self.channel, rest
+        )
+    }
+
+    /// Uploads a packaged chart (`helm package`).
+    pub async fn push(&self, chart: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        let chart_yaml = archive::read_entry(chart, ArchiveKind::TarGz, |entry| {
+            entry.ends_with("Chart.yaml") && entry.components().count() == 2
+        })?
+        .ok_or(format!("No Chart.yaml found in {}", chart.display()))?;
+        let mut metadata: ChartVersion = serde_yaml::from_slice(&chart_yaml)?;
+        let file_name = chart
+            .file_name()
+            .map(|s| s.to_string_lossy().to_string())
+            .ok_or("File name not found")?;
+        let content = tokio::fs::read(chart).await?;
+        metadata.digest = Some(hex::encode(Sha256::digest(&content)));
+
+        let url = gitlab_api_url(&format!(
+            "/projects/{}/packages/helm/api/{}/charts",
+            &self.project_id, &self.channel
+        ))?;
+        eprintln!(
+            "Pushing {} {} to channel {}",
+            &metadata.name, &metadata.version, &self.channel
+        );
+        let form = Form::new().part("chart", Part::bytes(content).file_name(file_name));
+        let response = with_basic_auth(httpclient().post(url))
+            .multipart(form)
+            .send()
+            .await?;
+        let status = response.status();
+        if status != 201 {
+            return Err(format!(
+                "Push failed with status: {}, and message: {}",
+                status,
+                response.text().await?
+            )
+            .into());
+        }
+        let msg = PrintOutput {
+            status: "ok".to_string(),
+            output: metadata,
+        };
+        println!("{}", serde_json::to_string_pretty(&msg)?);
+        Ok(())
+    }
+
+    /// Fetches the `index.yaml` of the channel.
+    pub async fn index(&self) -> Result<HelmIndex, Box<dyn std::error::Error>> {
+        let url = gitlab_api_url(&self.channel_path("/index.yaml"))?;
+        let content = fetch_bytes(with_basic_auth(httpclient().get(url))).await?;
+        Ok(serde_yaml::from_slice(&content)?)
+    }
+
+    /// Downloads a chart version to `output`, checking the digest from the index.
+    pub async fn pull(
+        &self,
+        name: &str,
+        version: Option<&str>,
+        strategy: &VersionStrategy,
+        output: PathBuf,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let target = DownloadTarget::from_path(&output)?;
+        let mut index = self.index().await?;
+        let versions = index.entries.remove(name).ok_or(format!(
+            "PackageNotFound: no chart {} in {}",
+            name, &self.channel
+        ))?;
+        let chart = match version {
+            Some(version) => versions.into_iter().find(|c| c.version == version),
+            None => strategy.select(versions, |c| &c.version, |c| c.created.as_deref()),
+        }
+        .ok_or(format!(
+            "PackageNotFound: no version of {} matches {}",
+            name,
+            version.map(String::from).unwrap_or(strategy.to_string())
+        ))?;
+        let chart_url = chart
+            .urls
+            .first()
+            .ok_or(format!("No url for {} {}", name, &chart.version))?;
+        let url = self.chart_url(chart_url)?;
+        let file_name = chart_url
+            .rsplit('/')
+            .next()
+            .unwrap_or(chart_url)
+            .to_string();
+
+        let content = fetch_bytes(with_basic_auth(httpclient().get(url))).await?;
+        verify_sha256(&file_name, &content, chart.digest.as_deref())?;
+        let output_file = match target {
+            DownloadTarget::Stdout => {
+                std::io::stdout().write_all(&content)?;
+                return Ok(());
+            }
+            DownloadTarget::Dir(dir) => dir.join(&file_name),
+            DownloadTarget::File(file) => file,
+        };
+        std::fs::write(&output_file, &content)?;
+        let msg = PrintOutput {
+            status: "ok".to_string(),
+            output: PackageDownloadOutput {
+                package_name: chart.name,
+                package_version: chart.version,
+                files: vec![output_file.to_string_lossy().to_string()],
+                extracted: None,
+            },
+        };
+        println!("{}", serde_json::to_string_pretty(&msg)?);
+        Ok(())
+    }
+
+    /// Resolves a chart url from the index, which is relative to the channel.
+    fn chart_url(&self, url: &str) -> Result<Url, Box<dyn std::error::Error>> {
+        if url.starts_with("https://") || url.starts_with("http://") {
+            return Ok(Url::parse(url)?);
+        }
+        gitlab_api_url(&self.channel_path(&format!("/{}", url.trim_start_matches('/'))))
+    }
+}
+
+#[cfg(test)]
+mod helm_tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_index() {
+        let index = r#"
+apiVersion: v1
+entries:
+  mychart:
+  - name: mychart
+    version: 1.2.0
+    appVersion: "2.0"
+    created: "2025-02-01T10:00:00.000000Z"
+    digest: abc
+    urls:
+    - charts/mychart-1.2.0.tgz
+  - name: mychart
+    version: 1.10.0
+    created: "2025-01-01T10:00:00.000000Z"
+    urls:
+    - charts/mychart-1.10.0.tgz
+generated: "2025-02-01T10:00:00Z"
+serverInfo:
+  contextPath: /api/v4/projects/1/packages/helm
+"#;
+        let index: HelmIndex = serde_yaml::from_str(index).unwrap();
+        let versions = &index.entries["mychart"];
+        assert_eq!(versions[0].app_version.as_deref(), Some("2.0"));
+        let newest = VersionStrategy::CreatedAt
+            .select(versions, |c| &c.version, |c| c.created.as_deref())
+            .unwrap();
+        assert_eq!(newest.version, "1.2.0");
+        let highest = VersionStrategy::SemverMax
+            .select(versions, |c| &c.version, |c| c.created.as_deref())
+            .unwrap();
+        assert_eq!(highest.version, "1.10.0");
+    }
+}
@@ -0,0 +1,228 @@
+//! Install binaries published in the generic package registry.
+//!
+//! The file matching the current architecture (see [`osarch::current_arch`]) is downloaded,
+//! verified against the sha256 reported by GitLab, marked executable and moved into place.
+
+use super::checksums::is_sha256sums_file;
+use super::packages::{
+    GenericPackageOp, ProjectPackageListOp, VersionStrategy, download_file, file_sha256,
+    generic_package_file_url, latest_uploads,
+};
+use crate::endpoints::PrintOutput;
+use crate::models::PackageFileInfo;
+use crate::signing::SIGNATURE_SUFFIX;
+use regex::Regex;
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+
+/// Project that glabu publishes its own prebuilt binaries to.
+pub const SELF_UPDATE_PROJECT: &str = "puterize/prebuilt";
+/// Package name of the glabu binaries in [`SELF_UPDATE_PROJECT`].
+pub const SELF_UPDATE_PACKAGE: &str = "glabu";
+
+/// Result of installing a binary.
+#[derive(Debug, Serialize)]
+pub struct InstallOutput {
+    pub package_name: String,
+    pub package_version: String,
+    pub file_name: String,
+    pub path: String,
+    /// False if the installed binary was already identical
+    pub updated: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct BinaryInstallOp {
+    /// Full path to the project, for example: owner/project
+    pub project: String,
+    pub package_name: String,
+    /// Exact version to install, takes precedence over `version_strategy`
+    pub package_version: Option<String>,
+    pub version_strategy: VersionStrategy,
+    /// Additional filename regex, for when several files match the current architecture
+    pub pattern: Option<String>,
+    /// Where the binary is installed to
+    pub target: PathBuf,
+}
+
+impl BinaryInstallOp {
+    /// Installs `package_name` as `<bin_dir>/<binary_name>`.
+    pub fn new(project: &str, package_name: &str, bin_dir: &Path, binary_name: &str) -> Self {
+        Self {
+            project: project.to_string(),
+            package_name: package_name.to_string(),
+            package_version: None,
+            version_strategy: VersionStrategy::default(),
+            pattern: None,
+            target: bin_dir.join(binary_name),
+        }
+    }
+
+    /// Replaces the currently running glabu binary with the latest published one.
+    pub fn self_update() -> Result<Self, Box<dyn std::error::Error>> {
+        let current_exe = std::env::current_exe()?.canonicalize()?;
+        Ok(Self {
+            project: SELF_UPDATE_PROJECT.to_string(),
+            package_name: SELF_UPDATE_PACKAGE.to_string(),
+            package_version: None,
+            version_strategy: VersionStrategy::CreatedAt,
+            pattern: None,
+            target: current_exe,
+        })
+    }
+
+    pub fn package_version(mut self, package_version: Option<String>) -> Self {
+        self.package_version = package_version;
+        self
+    }
+    pub fn version_strategy(mut self, version_strategy: VersionStrategy) -> Self {
+        self.version_strategy = version_strategy;
+        self
+    }
+    pub fn pattern(mut self, pattern: Option<String>) -> Self {
+        self.pattern = pattern;
+        self
+    }
+
+    pub async fn run(self) -> Result<(), Box<dyn std::error::Error>> {
+        let project_id = urlencoding::encode(&self.project).to_string();
+        let package = GenericPackageOp::new(&project_id, &self.package_name, "")
+            .package_version(self.package_version.clone())
+            .version_strategy(self.version_strategy.clone())
+            .resolve_package()
+            .await?;
+        let files = ProjectPackageListOp::new(&project_id)
+            .package_files(&package)
+            .await?;
+        let file = self.select_file(files)?;
+        let sha256 = file
+            .file_sha256
+            .clone()
+            .ok_or(format!("No sha256 reported for {}", &file.file_name))?;
+
+        let updated = !(self.target.is_file() && file_sha256(&self.target)? == sha256);
+        if updated {
+            let bin_dir = self.target.parent().ok_or("Invalid install path")?;
+            std::fs::create_dir_all(bin_dir)?;
+            // download next to the target, so that the final rename is atomic
+            let tmp_file = bin_dir.join(format!(".{}.glabu-download", &file.file_name));
+            let url = generic_package_file_url(
+                &project_id,
+                &package.name,
+                &package.version,
+                &file.file_name,
+            )?;
+            eprintln!("Downloading {}", &url);
+            download_file(url, &tmp_file).await?;
+            let downloaded_sha256 = file_sha256(&tmp_file)?;
+            if downloaded_sha256 != sha256 {
+                std::fs::remove_file(&tmp_file)?;
+                return Err(format!(
+                    "ChecksumMismatch: {} has sha256 {}, expected {}",
+                    &file.file_name, downloaded_sha256, sha256
+                )
+                .into());
+            }
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::PermissionsExt;
+                std::fs::set_permissions(&tmp_file, std::fs::Permissions::from_mode(0o755))?;
+            }
+            std::fs::rename(&tmp_file, &self.target)?;
+            eprintln!(
+                "Installed {} {} to {}",
+                &package.name,
+                &package.version,
+                self.target.display()
+            );
+        } else {
+            eprintln!("{} is already up to date", self.target.display());
+        }
+
+        let msg = PrintOutput {
+            status: "ok".to_string(),
+            output: InstallOutput {
+                package_name: package.name,
+                package_version: package.version,
+                file_name: file.file_name,
+                path: self.target.to_string_lossy().to_string(),
+                updated,
+            },
+        };
+        println!("{}", serde_json::to_string_pretty(&msg)?);
+        Ok(())
+    }
+
+    /// Picks the single file built for the current architecture.
+    fn select_file(
+        &self,
+        files: Vec<PackageFileInfo>,
+    ) -> Result<PackageFileInfo, Box<dyn std::error::Error>> {
+        let pattern = self.pattern.as_deref().map(Regex::new).transpose()?;
+        select_arch_file(files, &osarch::current_arch(), pattern.as_ref())
+    }
+}
+
+/// Picks the single file matching `arch` (and `pattern`, if given).
+/// Signatures and checksum files are not binaries, even if their names mention the arch.
+fn select_arch_file(
+    files: Vec<PackageFileInfo>,
+    arch: &Regex,
+    pattern: Option<&Regex>,
+) -> Result<PackageFileInfo, Box<dyn std::error::Error>> {
+    let mut candidates: Vec<PackageFileInfo> = latest_uploads(files)
+        .into_iter()
+        .filter(|f| !f.file_name.ends_with(SIGNATURE_SUFFIX) && !is_sha256sums_file(&f.file_name))
+        .filter(|f| arch.is_match(&f.file_name))
+        .filter(|f| pattern.is_none_or(|p| p.is_match(&f.file_name)))
+        .collect();
+    if candidates.len() > 1 {
+        return Err(format!(
+            "AmbiguousFile: several files match the current architecture, use a regex to pick one: {}",
+            candidates
+                .iter()
+                .map(|f| f.file_name.as_str())
+                .collect::<Vec<_>>()
+                .join(", ")
+        )
+        .into());
+    }
+    candidates
+        .pop()
+        .ok_or_else(|| "NoFileForArch: no file matches the current architecture".into())
+}
+
+#[cfg(test)]
+mod install_tests {
+    use super::*;
+
+    fn file(id: u64, file_name: &str) -> PackageFileInfo {
+        PackageFileInfo {
+            id,
+            package_id: 1,
+            created_at: "2025-01-01T00:00:00Z".to_string(),
+            file_name: file_name.to_string(),
+            size: None,
+            file_md5: None,
+            file_sha1: None,
+            file_sha256: None,
+            version: None,
+            name: None,
+        }
+    }
+
+    #[test]
+    fn test_select_signed_file() {
+        let files = vec![
+            file(1, "glabu-x86_64"),
+            file(2, "glabu-x86_64.minisig"),
+            file(3, "glabu-aarch64"),
+            file(4, "glabu-aarch64.minisig"),
+            file(5, "SHA256SUMS"),
+            file(6, "SHA256SUMS.minisig"),
+        ];
+        let arch = Regex::new("x86_64|amd64").unwrap();
+        let selected = select_arch_file(files, &arch, None).unwrap();
+        assert_eq!(selected.file_name, "glabu-x86_64");
+    }
+}
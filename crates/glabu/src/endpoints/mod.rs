@@ -1,8 +1,23 @@
+pub mod checksums;
+pub mod copy;
+pub mod debian;
+pub mod helm;
+pub mod install;
+pub mod npm;
 pub mod packages;
 pub mod profiles;
 pub mod projects;
+pub mod pypi;
+pub mod registry;
 pub mod releases;
+pub mod rpm;
 pub mod setup;
+pub mod sync;
+pub mod terraform;
+#[cfg(test)]
+mod test_gitlab;
+pub mod usage;
+pub mod watch;
 
 use serde::Serialize;
 #[derive(Serialize)]
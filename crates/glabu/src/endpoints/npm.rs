@@ -0,0 +1,255 @@
+//! Publish to the npm registry of a GitLab project and manage its dist-tags.
+//!
+//! `npm-publish` sends the same document as `npm publish`: the `package.json` of the
+//! tarball as the version entry, the dist-tag, and the tarball as a base64 attachment.
+//! See gitlab api doc: https://docs.gitlab.com/api/packages/npm/
+
+use super::setup::{gitlab_api_url, gitlab_token, httpclient};
+use crate::archive::{self, ArchiveKind};
+use crate::endpoints::PrintOutput;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use serde::Serialize;
+use serde_json::{Value, json};
+use sha1::Sha1;
+use sha2::{Digest, Sha512};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// Result of publishing a tarball.
+#[derive(Debug, Serialize)]
+pub struct NpmPublishOutput {
+    pub package_name: String,
+    pub package_version: String,
+    pub tag: String,
+    pub shasum: String,
+    pub integrity: String,
+}
+
+/// Builds the registry document for publishing `tarball` with `package_json` under `tag`.
+///
+/// `tarball_url` is where the registry will serve the tarball from.
+pub fn publish_document(
+    package_json: &Value,
+    tarball: &[u8],
+    tag: &str,
+    tarball_url: &str,
+) -> Result<Value, String> {
+    let name = package_json["name"]
+        .as_str()
+        .ok_or("InvalidPackageJson: name is missing")?;
+    let version = package_json["version"]
+        .as_str()
+        .ok_or("InvalidPackageJson: version is missing")?;
+    let mut version_doc = package_json.clone();
+    version_doc["_id"] = json!(format!("{}@{}", name, version));
+    version_doc["dist"] = json!({
+        "shasum": hex::encode(Sha1::digest(tarball)),
+        "integrity": format!("sha512-{}", BASE64.encode(Sha512::digest(tarball))),
+        "tarball": tarball_url,
+    });
+    Ok(json!({
+        "_id": name,
+        "name": name,
+        "description": package_json["description"],
+        "dist-tags": { tag: version },
+        "versions": { version: version_doc },
+        "_attachments": {
+            tarball_file_name(name, version): {
+                "content_type": "application/octet-stream",
+                "data": BASE64.encode(tarball),
+                "length": tarball.len(),
+            }
+        },
+    }))
+}
+
+/// File name of a tarball in the registry, e.g. `name-1.0.0.tgz` for `@scope/name`.
+pub fn tarball_file_name(name: &str, version: &str) -> String {
+    let base = name.rsplit('/').next().unwrap_or(name);
+    format!("{}-{}.tgz", base, version)
+}
+
+#[derive(Debug, Clone)]
+pub struct NpmOp {
+    ///  Your project ID or URL-encoded path
+    pub project_id: String,
+}
+
+impl NpmOp {
+    pub fn new(project_id: &str) -> Self {
+        Self {
+            project_id: project_id.to_string(),
+        }
+    }
+
+    /// Api path of a package in the npm registry of the project.
+    fn package_path(&self, package_name: &str) -> String {
+        format!(
+            "/projects/{}/packages/npm/{}",
+            &self.project_id,
+            encode_package_name(package_name)
+        )
+    }
+
+    fn dist_tags_path(&self, package_name: &str, tag: Option<&str>) -> String {
+        let tag = tag.map(|t| format!("/{}", t)).unwrap_or_default();
+        format!(
+            "/projects/{}/packages/npm/-/package/{}/dist-tags{}",
+            &self.project_id,
+            encode_package_name(package_name),
+            tag
+        )
+    }
+
+    /// Publishes an `npm pack` tarball under `tag`.
+    pub async fn publish(
+        &self,
+        tarball: &Path,
+        tag: &str,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let package_json = archive::read_entry(tarball, ArchiveKind::TarGz, |entry| {
+            entry.ends_with("package.json") && entry.components().count() == 2
+        })?
+        .ok_or(format!("No package.json found in {}", tarball.display()))?;
+        let package_json: Value = serde_json::from_slice(&package_json)?;
+        let content = tokio::fs::read(tarball).await?;
+        let name = package_json["name"].as_str().unwrap_or_default();
+        let version = package_json["version"].as_str().unwrap_or_default();
+        let tarball_url = gitlab_api_url(&format!(
+            "{}/-/{}",
+            self.package_path(name),
+            tarball_file_name(name, version)
+        ))?;
+        let document = publish_document(&package_json, &content, tag, tarball_url.as_str())?;
+
+        eprintln!("Publishing {}@{} with tag {}", name, version, tag);
+        let url = gitlab_api_url(&self.package_path(name))?;
+        let response = httpclient()
+            .put(url)
+            .bearer_auth(gitlab_token())
+            .json(&document)
+            .send()
+            .await?;
+        let status = response.status();
+        if !status.is_success() {
+            return Err(format!(
+                "Publish failed with status: {}, and message: {}",
+                status,
+                response.text().await?
+            )
+            .into());
+        }
+        let dist = &document["versions"][version]["dist"];
+        let msg = PrintOutput {
+            status: "ok".to_string(),
+            output: NpmPublishOutput {
+                package_name: name.to_string(),
+                package_version: version.to_string(),
+                tag: tag.to_string(),
+                shasum: dist["shasum"].as_str().unwrap_or_default().to_string(),
+                integrity: dist["integrity"].as_str().unwrap_or_default().to_string(),
+            },
+        };
+        println!("{}", serde_json::to_string_pretty(&msg)?);
+        Ok(())
+    }
+
+    /// Lists the dist-tags of a package, tag -> version.
+    pub async fn dist_tags(
+        &self,
+        package_name: &str,
+    ) -> Result<BTreeMap<String, String>, Box<dyn std::error::Error>> {
+        let url = gitlab_api_url(&self.dist_tags_path(package_name, None))?;
+        let response = httpclient()
+            .get(url)
+            .bearer_auth(gitlab_token())
+            .send()
+            .await?;
+        let status = response.status();
+        if status == 404 {
+            return Err(format!("PackageNotFound: {}", package_name).into());
+        }
+        if !status.is_success() {
+            return Err(format!("Listing dist-tags failed with status: {}", status).into());
+        }
+        Ok(response.json().await?)
+    }
+
+    /// Points `tag` at `version`.
+    pub async fn add_dist_tag(
+        &self,
+        package_name: &str,
+        version: &str,
+        tag: &str,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let url = gitlab_api_url(&self.dist_tags_path(package_name, Some(tag)))?;
+        let response = httpclient()
+            .put(url)
+            .bearer_auth(gitlab_token())
+            .json(&version)
+            .send()
+            .await?;
+        let status = response.status();
+        if !status.is_success() {
+            return Err(format!(
+                "Adding dist-tag failed with status: {}, and message: {}",
+                status,
+                response.text().await?
+            )
+            .into());
+        }
+        Ok(())
+    }
+
+    pub async fn remove_dist_tag(
+        &self,
+        package_name: &str,
+        tag: &str,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let url = gitlab_api_url(&self.dist_tags_path(package_name, Some(tag)))?;
+        let response = httpclient()
+            .delete(url)
+            .bearer_auth(gitlab_token())
+            .send()
+            .await?;
+        let status = response.status();
+        if !status.is_success() {
+            return Err(format!(
+                "Removing dist-tag failed with status: {}, and message: {}",
+                status,
+                response.text().await?
+            )
+            .into());
+        }
+        Ok(())
+    }
+}
+
+/// Encodes a package name for a path, scoped names are sent as `@scope%2Fname`.
+fn encode_package_name(package_name: &str) -> String {
+    urlencoding::encode(package_name).replace("%40", "@")
+}
+
+#[cfg(test)]
+mod npm_tests {
+    use super::*;
+
+    #[test]
+    fn test_publish_document() {
+        let package_json = json!({"name": "@puterize/ui", "version": "1.4.0", "description": "UI"});
+        let doc =
+            publish_document(&package_json, b"tarball", "next", "https://x/ui-1.4.0.tgz").unwrap();
+        assert_eq!(doc["dist-tags"]["next"], "1.4.0");
+        let version = &doc["versions"]["1.4.0"];
+        assert_eq!(version["_id"], "@puterize/ui@1.4.0");
+        assert_eq!(
+            version["dist"]["shasum"],
+            hex::encode(Sha1::digest(b"tarball"))
+        );
+        let attachment = &doc["_attachments"]["ui-1.4.0.tgz"];
+        assert_eq!(attachment["data"], BASE64.encode(b"tarball"));
+        assert_eq!(attachment["length"], 7);
+        assert!(publish_document(&json!({"name": "x"}), b"", "latest", "").is_err());
+    }
+}
@@ -45,18 +45,30 @@
 //! Note the layout above is just conceptual, the actual response from the API is different.
 //! See the [GitLab API documentation](https://docs.gitlab.com/user/packages/generic_packages) for more details.
 
-use super::setup::{gitlab_api_url_with_query, gitlab_token, httpclient};
+use super::checksums::{SHA256SUMS, format_sha256sums, is_sha256sums_file};
+use super::profiles::groups_get;
+use super::setup::{gitlab_api_url_with_query, gitlab_host, gitlab_token, httpclient};
+use crate::archive::{self, ArchiveKind};
 use crate::endpoints::PrintOutput;
-use crate::endpoints::setup::gitlab_api_url;
+use crate::endpoints::setup::{GitlabInstance, gitlab_api_url};
+pub use crate::models::PackageType;
 use crate::models::{PackageFileInfo, PackageInfo, SortDirection};
+use crate::signing::{self, PublicKey, SIGNATURE_SUFFIX, SecretKey};
+use clap::ValueEnum;
 use regex::Regex;
-use reqwest::Url;
+use reqwest::header::{ETAG, IF_NONE_MATCH};
+use reqwest::{Body, RequestBuilder, StatusCode, Url};
+use semver::{Version, VersionReq};
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use std::borrow::Borrow;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt;
 use std::fs::File;
 use std::io::Write;
 use std::path::{Path, PathBuf};
+use std::str::FromStr;
 
 trait PackageFileFilter {
     fn filter(&self, files: &PackageFileInfo) -> bool;
@@ -110,25 +122,9 @@ pub enum ProjectPackageListOrderBy {
     Type,
 }
 
-/// Enum for package types.
-/// One of conan, maven, npm, pypi, composer, nuget, helm, terraform_module, or golang.
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
-#[serde(rename_all = "snake_case")]
-pub enum PackageType {
-    Conan,
-    Maven,
-    Npm,
-    Pypi,
-    Composer,
-    Nuget,
-    Helm,
-    TerraformModule,
-    Golang,
-}
-
 /// Enum for package status.
 /// One of default, hidden, processing, error, or pending_destruction.
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
 #[serde(rename_all = "snake_case")]
 pub enum PackageStatus {
     Default,
@@ -138,6 +134,179 @@ pub enum PackageStatus {
     PendingDestruction,
 }
 
+impl PackageStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PackageStatus::Default => "default",
+            PackageStatus::Hidden => "hidden",
+            PackageStatus::Processing => "processing",
+            PackageStatus::Error => "error",
+            PackageStatus::PendingDestruction => "pending_destruction",
+        }
+    }
+}
+
+/// The statuses a package can be uploaded with, the others are set by GitLab.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum UploadStatus {
+    Default,
+    Hidden,
+}
+
+impl From<UploadStatus> for PackageStatus {
+    fn from(status: UploadStatus) -> Self {
+        match status {
+            UploadStatus::Default => PackageStatus::Default,
+            UploadStatus::Hidden => PackageStatus::Hidden,
+        }
+    }
+}
+
+/// Strategy for picking a concrete package version when none is given explicitly.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum VersionStrategy {
+    /// The most recently created package.
+    #[default]
+    CreatedAt,
+    /// The highest version according to semver precedence.
+    SemverMax,
+    /// The highest version matching a semver requirement, e.g. `^1.4` or `~2.0`.
+    Requirement(VersionReq),
+}
+
+impl FromStr for VersionStrategy {
+    type Err = String;
+
+    /// Parses `created-at`, `semver-max`, or anything else as a semver requirement.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "created-at" | "created_at" => Ok(VersionStrategy::CreatedAt),
+            "semver-max" | "semver_max" => Ok(VersionStrategy::SemverMax),
+            req => VersionReq::parse(req)
+                .map(VersionStrategy::Requirement)
+                .map_err(|e| format!("Invalid version strategy or requirement {}: {}", req, e)),
+        }
+    }
+}
+
+impl fmt::Display for VersionStrategy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VersionStrategy::CreatedAt => write!(f, "created-at"),
+            VersionStrategy::SemverMax => write!(f, "semver-max"),
+            VersionStrategy::Requirement(req) => write!(f, "{}", req),
+        }
+    }
+}
+
+impl VersionStrategy {
+    /// Picks the item with the newest `created_at`, or the highest (matching) version.
+    /// Items whose version is not semver are ignored by the semver strategies.
+    pub fn select<T>(
+        &self,
+        items: impl IntoIterator<Item = T>,
+        version: impl Fn(&T) -> &str,
+        created_at: impl Fn(&T) -> Option<&str>,
+    ) -> Option<T> {
+        let items = items.into_iter();
+        match self {
+            VersionStrategy::CreatedAt => items.max_by(|a, b| created_at(a).cmp(&created_at(b))),
+            VersionStrategy::SemverMax | VersionStrategy::Requirement(_) => items
+                .filter_map(|item| parse_version(version(&item)).map(|v| (v, item)))
+                .filter(|(v, _)| match self {
+                    VersionStrategy::Requirement(req) => req.matches(v),
+                    _ => true,
+                })
+                .max_by(|(a, _), (b, _)| a.cmp(b))
+                .map(|(_, item)| item),
+        }
+    }
+}
+
+/// Parses a package version as semver, tolerating a leading `v` and
+/// shorthand versions such as `1.4` or `2`.
+/// Returns `None` for versions that are not semver at all (e.g. commit hashes).
+pub fn parse_version(version: &str) -> Option<Version> {
+    let version = version.trim().trim_start_matches('v');
+    if let Ok(v) = Version::parse(version) {
+        return Some(v);
+    }
+    let parts: Vec<&str> = version.split('.').collect();
+    if parts.len() > 2 || parts.iter().any(|p| p.parse::<u64>().is_err()) {
+        return None;
+    }
+    let mut numbers = parts.iter().map(|p| p.parse::<u64>().unwrap());
+    Some(Version::new(
+        numbers.next().unwrap_or(0),
+        numbers.next().unwrap_or(0),
+        0,
+    ))
+}
+
+/// Sends `request` with `If-None-Match: etag`, if there is an `etag`.
+///
+/// Returns `None` when the server answers 304 Not Modified,
+/// otherwise the parsed body together with the new ETag.
+pub async fn get_if_changed<T: DeserializeOwned>(
+    mut request: RequestBuilder,
+    etag: Option<&str>,
+) -> Result<Option<(T, Option<String>)>, Box<dyn std::error::Error>> {
+    if let Some(etag) = etag {
+        request = request.header(IF_NONE_MATCH, etag);
+    }
+    let response = request.send().await?;
+    let status = response.status();
+    if status == StatusCode::NOT_MODIFIED {
+        return Ok(None);
+    }
+    if !status.is_success() {
+        return Err(format!("Listing packages failed with status: {}", status).into());
+    }
+    let etag = response
+        .headers()
+        .get(ETAG)
+        .and_then(|v| v.to_str().ok())
+        .map(String::from);
+    let body = serde_json::from_slice::<T>(&response.bytes().await?)?;
+    Ok(Some((body, etag)))
+}
+
+/// Collects the items of all pages, `list_page` gets the page number starting at 1.
+/// A page with fewer than `per_page` items is the last one.
+pub async fn list_pages<T, F, Fut>(
+    per_page: u64,
+    mut list_page: F,
+) -> Result<Vec<T>, Box<dyn std::error::Error>>
+where
+    F: FnMut(u64) -> Fut,
+    Fut: Future<Output = Result<Vec<T>, Box<dyn std::error::Error>>>,
+{
+    let mut items = vec![];
+    let mut page = 1;
+    loop {
+        let page_items = list_page(page).await?;
+        let n = page_items.len() as u64;
+        items.extend(page_items);
+        if n < per_page {
+            return Ok(items);
+        }
+        page += 1;
+    }
+}
+
+/// Picks the package that best satisfies `strategy` among `packages`.
+/// Only packages named exactly `name` are considered, since GitLab filters names fuzzily.
+fn select_package(
+    packages: Vec<PackageInfo>,
+    name: Option<&str>,
+    strategy: &VersionStrategy,
+) -> Option<PackageInfo> {
+    let candidates = packages
+        .into_iter()
+        .filter(|p| name.is_none_or(|name| p.name == name));
+    strategy.select(candidates, |p| &p.version, |p| p.created_at.as_deref())
+}
+
 /// Struct for listing packages of a project.
 /// This struct holds the necessary information to list packages in a project.
 /// See https://docs.gitlab.com/api/packages/#list-packages
@@ -149,7 +318,7 @@ pub struct ProjectPackageListOp {
     order_by: Option<ProjectPackageListOrderBy>,
     /// The direction of the order, either asc (default) for ascending order or desc for descending order.
     sort: Option<SortDirection>,
-    /// Filter the returned packages by type. One of conan, maven, npm, pypi, composer, nuget, helm, terraform_module, or golang.
+    /// Filter the returned packages by type, e.g. generic, npm, pypi, helm, debian or rpm.
     package_type: Option<PackageType>,
     /// Filter the project packages with a fuzzy search by name.
     package_name: Option<String>,
@@ -176,10 +345,10 @@ impl ProjectPackageListOp {
     ///
     /// A `ProjectPackageList` instance.
     pub fn new(id: impl ToString) -> Self {
-		let mut id = id.to_string();
-		if id.contains("/") {
-			id = urlencoding::encode(id.as_str()).to_string();
-		}
+        let mut id = id.to_string();
+        if id.contains("/") {
+            id = urlencoding::encode(id.as_str()).to_string();
+        }
         Self {
             id,
             order_by: None,
@@ -252,26 +421,8 @@ impl ProjectPackageListOp {
 
     pub async fn list(&self) -> Result<Vec<PackageInfo>, Box<dyn std::error::Error>> {
         eprintln!("list: {:?}", self);
-        let self_json = serde_json::to_string(&self)?;
-        eprintln!("self_json: {}", self_json);
-        let self_map: HashMap<String, serde_json::Value> = serde_json::from_str(&self_json)?;
-        let query: Vec<(&str, String)> = self_map
-            .iter()
-            .filter_map(|(key, value)| {
-                if value.is_null() {
-                    return None;
-                }
-                Some((key.as_str(), value.to_string().replace("\"", "")))
-            })
-            .collect();
-        let query_with_str_value = query
-            .iter()
-            .map(|(k, v)| {
-				eprintln!("{}: {}", k, v);
-				(*k, v.as_str())
-	})
-            .collect::<Vec<(&str, &str)>>();
-        let json = packages_get_helper(&self.id, "", &query_with_str_value).await?;
+        let query = list_query(self)?;
+        let json = packages_get_helper(&self.id, "", &query).await?;
         eprintln!(
             "packages_get_helper json: {}",
             String::from_utf8_lossy(&json)
@@ -280,6 +431,61 @@ impl ProjectPackageListOp {
         Ok(packages)
     }
 
+    /// Lists packages like [`Self::list`], unless they are unchanged since `etag`.
+    ///
+    /// Returns `None` when GitLab answers 304 Not Modified,
+    /// otherwise the packages together with the new ETag.
+    pub async fn list_if_changed(
+        &self,
+        etag: Option<&str>,
+    ) -> Result<Option<(Vec<PackageInfo>, Option<String>)>, Box<dyn std::error::Error>> {
+        let query = list_query(self)?;
+        let url = gitlab_api_url_with_query(&format!("/projects/{}/packages", &self.id), &query)?;
+        let request = httpclient()
+            .get(url)
+            .header("Private-Token", gitlab_token());
+        get_if_changed(request, etag).await
+    }
+
+    /// Lists packages from all pages, starting at page 1.
+    pub async fn list_all(&self) -> Result<Vec<PackageInfo>, Box<dyn std::error::Error>> {
+        let per_page = self.per_page.unwrap_or(100);
+        let op = self.clone().per_page(Some(per_page));
+        list_pages(per_page, |page| {
+            let op = op.clone().page(Some(page));
+            async move { op.list().await }
+        })
+        .await
+    }
+
+    /// Resolves the package version to use according to `strategy`.
+    ///
+    /// GitLab matches `package_name` fuzzily, so only packages with exactly
+    /// that name are considered. `CreatedAt` looks at the newest page first,
+    /// the semver strategies go through all pages of versions.
+    pub async fn resolve(
+        &self,
+        strategy: &VersionStrategy,
+    ) -> Result<PackageInfo, Box<dyn std::error::Error>> {
+        if *strategy == VersionStrategy::CreatedAt {
+            let mut op = self.clone();
+            op.latest();
+            let newest = op.per_page(Some(100)).list().await?;
+            if let Some(package) = select_package(newest, self.package_name.as_deref(), strategy) {
+                return Ok(package);
+            }
+        }
+        let packages = self.clone().package_version(None).list_all().await?;
+        select_package(packages, self.package_name.as_deref(), strategy).ok_or_else(|| {
+            format!(
+                "PackageNotFound: no version of {} satisfies {}",
+                self.package_name.as_deref().unwrap_or("package"),
+                strategy
+            )
+            .into()
+        })
+    }
+
     pub async fn first(&self) -> Result<PackageInfo, Box<dyn std::error::Error>> {
         let mut packages = self.list().await?;
         let res = packages
@@ -298,14 +504,26 @@ impl ProjectPackageListOp {
         Ok(package)
     }
 
+    /// Lists the files of a package, from all pages.
     pub async fn package_files(
         &self,
         package: &PackageInfo,
     ) -> Result<Vec<PackageFileInfo>, Box<dyn std::error::Error>> {
         let path = format!("/{}/package_files", &package.id);
-        let json = packages_get_helper(self.id.clone(), &path, vec![("", "")]).await?;
-		eprintln!("package_files json: {}", String::from_utf8_lossy(&json));
-        let package_files = serde_json::from_slice::<Vec<PackageFileInfo>>(&json)?;
+        let per_page = 100;
+        let package_files = list_pages(per_page, |page| {
+            let query = [
+                ("per_page", per_page.to_string()),
+                ("page", page.to_string()),
+            ];
+            let path = &path;
+            async move {
+                let json = packages_get_helper(self.id.clone(), path, &query).await?;
+                eprintln!("package_files json: {}", String::from_utf8_lossy(&json));
+                Ok(serde_json::from_slice::<Vec<PackageFileInfo>>(&json)?)
+            }
+        })
+        .await?;
         let package_files = package_files
             .into_iter()
             .map(|mut package_file| {
@@ -337,6 +555,218 @@ impl ProjectPackageListOp {
     }
 }
 
+/// Turns the non-null fields of a list operation into query parameters.
+fn list_query<T: Serialize>(op: &T) -> Result<Vec<(String, String)>, Box<dyn std::error::Error>> {
+    let op_json = serde_json::to_string(op)?;
+    let op_map: HashMap<String, serde_json::Value> = serde_json::from_str(&op_json)?;
+    let query = op_map
+        .into_iter()
+        .filter(|(_, value)| !value.is_null())
+        .map(|(key, value)| (key, value.to_string().replace("\"", "")))
+        .collect();
+    Ok(query)
+}
+
+/// Struct for listing packages of a group, including its subgroups by default.
+/// See https://docs.gitlab.com/api/packages/#for-a-group
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GroupPackageListOp {
+    /// ID or URL-encoded path of the group.
+    #[serde(skip)]
+    id: String,
+    /// Exclude packages of projects in subgroups (default: false).
+    exclude_subgroups: Option<bool>,
+    /// The field to use as order. One of created_at (default), name, version, or type.
+    order_by: Option<ProjectPackageListOrderBy>,
+    /// The direction of the order, either asc (default) for ascending order or desc for descending order.
+    sort: Option<SortDirection>,
+    /// Filter the returned packages by type.
+    package_type: Option<PackageType>,
+    /// Filter the group packages with a fuzzy search by name.
+    package_name: Option<String>,
+    /// Filter the returned packages by status.
+    status: Option<PackageStatus>,
+    /// Number of items per page (default: 20, max 100).
+    per_page: Option<u64>,
+    /// Page number (default: 1).
+    page: Option<u64>,
+}
+
+impl GroupPackageListOp {
+    pub fn new(id: impl ToString) -> Self {
+        let mut id = id.to_string();
+        if id.contains("/") {
+            id = urlencoding::encode(id.as_str()).to_string();
+        }
+        Self {
+            id,
+            exclude_subgroups: None,
+            order_by: None,
+            sort: None,
+            package_type: None,
+            package_name: None,
+            status: None,
+            per_page: Some(100),
+            page: None,
+        }
+    }
+
+    pub fn exclude_subgroups(mut self, exclude_subgroups: Option<bool>) -> Self {
+        self.exclude_subgroups = exclude_subgroups;
+        self
+    }
+    pub fn order_by(mut self, order_by: Option<ProjectPackageListOrderBy>) -> Self {
+        self.order_by = order_by;
+        self
+    }
+    pub fn sort(mut self, sort: Option<SortDirection>) -> Self {
+        self.sort = sort;
+        self
+    }
+    pub fn package_type(mut self, package_type: Option<PackageType>) -> Self {
+        self.package_type = package_type;
+        self
+    }
+    pub fn package_name(mut self, package_name: Option<String>) -> Self {
+        self.package_name = package_name;
+        self
+    }
+    pub fn status(mut self, status: Option<PackageStatus>) -> Self {
+        self.status = status;
+        self
+    }
+    pub fn per_page(mut self, per_page: Option<u64>) -> Self {
+        self.per_page = per_page;
+        self
+    }
+    pub fn page(mut self, page: Option<u64>) -> Self {
+        self.page = page;
+        self
+    }
+
+    pub async fn list(&self) -> Result<Vec<PackageInfo>, Box<dyn std::error::Error>> {
+        let query = list_query(self)?;
+        let url = gitlab_api_url_with_query(&format!("/groups/{}/packages", self.id), &query)?;
+        let response = httpclient()
+            .get(url)
+            .header("Private-Token", gitlab_token())
+            .send()
+            .await?;
+        let status = response.status();
+        let json = response.bytes().await?;
+        if status != 200 {
+            return Err(format!(
+                "GroupPackageListErr: {}",
+                String::from_utf8(json.to_vec()).unwrap_or(status.to_string())
+            )
+            .into());
+        }
+        let packages = serde_json::from_slice::<Vec<PackageInfo>>(&json)?;
+        Ok(packages)
+    }
+
+    /// Lists packages from all pages, starting at page 1.
+    pub async fn list_all(&self) -> Result<Vec<PackageInfo>, Box<dyn std::error::Error>> {
+        let per_page = self.per_page.unwrap_or(100);
+        let op = self.clone().per_page(Some(per_page));
+        list_pages(per_page, |page| {
+            let op = op.clone().page(Some(page));
+            async move { op.list().await }
+        })
+        .await
+    }
+}
+
+/// A package found by [`packages_find`], with all its versions in one project.
+#[derive(Debug, Serialize)]
+pub struct PackageMatch {
+    pub project_id: Option<u64>,
+    pub project_path: Option<String>,
+    pub package_name: String,
+    pub package_type: Option<PackageType>,
+    /// All versions, newest first
+    pub versions: Vec<String>,
+    pub latest_version: String,
+    /// Files of the latest version
+    pub latest_files: Vec<String>,
+}
+
+/// Finds packages by (fuzzy) name in a group and its subgroups.
+/// Without a group, all top level groups the current user is a member of are searched.
+/// GitLab has no package listing across personal namespaces,
+/// so packages of projects outside groups are not found.
+pub async fn packages_find(
+    package_name: &str,
+    group: Option<&str>,
+    package_type: Option<PackageType>,
+    strategy: &VersionStrategy,
+) -> Result<Vec<PackageMatch>, Box<dyn std::error::Error>> {
+    let groups = match group {
+        Some(group) => vec![group.to_string()],
+        None => list_pages(100, |page| async move {
+            groups_get(&[
+                ("top_level_only", "true".to_string()),
+                ("per_page", "100".to_string()),
+                ("page", page.to_string()),
+            ])
+            .await
+        })
+        .await?
+        .into_iter()
+        .map(|g| g.full_path)
+        .collect(),
+    };
+    let mut found: BTreeMap<(String, String), Vec<PackageInfo>> = BTreeMap::new();
+    for group in &groups {
+        eprintln!("Searching packages in group {}", group);
+        let packages = GroupPackageListOp::new(group)
+            .package_name(Some(package_name.to_string()))
+            .package_type(package_type.clone())
+            .order_by(Some(ProjectPackageListOrderBy::CreatedAt))
+            .sort(Some(SortDirection::Desc))
+            .list_all()
+            .await?;
+        for package in packages {
+            let Some(project_id) = package.project_id else {
+                eprintln!(
+                    "Skipping {}@{} without a project id",
+                    package.name, package.version
+                );
+                continue;
+            };
+            found
+                .entry((project_id.to_string(), package.name.clone()))
+                .or_default()
+                .push(package);
+        }
+    }
+
+    let mut matches = vec![];
+    for ((project_id, name), packages) in found {
+        let versions = packages.iter().map(|p| p.version.clone()).collect();
+        let project_path = packages[0].project_path.clone();
+        let Some(latest) = select_package(packages, Some(&name), strategy) else {
+            continue;
+        };
+        let files = ProjectPackageListOp::new(&project_id)
+            .package_files(&latest)
+            .await?
+            .into_iter()
+            .map(|f| f.file_name)
+            .collect();
+        matches.push(PackageMatch {
+            project_id: latest.project_id,
+            project_path,
+            package_name: name,
+            package_type: latest.package_type,
+            versions,
+            latest_version: latest.version,
+            latest_files: files,
+        });
+    }
+    Ok(matches)
+}
+
 /// Info need for uploading/downloading generic package files.
 /// See gitlab api doc: https://docs.gitlab.com/user/packages/generic_packages/
 #[derive(Debug, Clone)]
@@ -347,10 +777,190 @@ pub struct GenericPackageOp {
     pub package_name: String,
     /// Version of your package, if not provided, the latest version will be used
     pub package_version: Option<String>,
+    /// How to pick the version when `package_version` is not provided
+    pub version_strategy: VersionStrategy,
+    /// Only consider packages of this type when looking up versions
+    pub package_type: Option<PackageType>,
+    /// Unpack downloaded tar, tar.gz, tar.xz and zip files and remove the archives
+    pub extract: bool,
+    /// Number of leading path components to drop when extracting
+    pub strip_components: usize,
+    /// Path of downloaded files relative to the output directory, see [`render_output_template`]
+    pub output_template: Option<String>,
+    /// Fail instead of overwriting a downloaded file that exists with different content
+    pub no_clobber: bool,
+    /// Instance to upload to, the one from `GITLAB_HOST` when not set
+    pub instance: Option<GitlabInstance>,
+    /// Status to upload files with, `hidden` keeps the version out of the UI
+    pub status: Option<UploadStatus>,
+    /// Key to sign uploaded files with, stored as `<file>.minisig` sidecars
+    pub signing_key: Option<SecretKey>,
+    /// Trusted key to verify the signatures of downloaded files with
+    pub public_key: Option<PublicKey>,
+    /// Refuse downloaded files without a valid signature
+    pub require_signature: bool,
     /// The file name
     pub file_name: String,
 }
 
+/// Where downloaded package files are written to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DownloadTarget {
+    /// Stream a single file to stdout
+    Stdout,
+    /// Write every file into an existing directory
+    Dir(PathBuf),
+    /// Write a single file to this path
+    File(PathBuf),
+}
+
+impl DownloadTarget {
+    /// `-` means stdout, an existing directory means directory mode,
+    /// and anything else is a file path whose parent directory must exist.
+    pub fn from_path(path: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        if path == Path::new("-") {
+            return Ok(DownloadTarget::Stdout);
+        }
+        if path.is_dir() {
+            return Ok(DownloadTarget::Dir(path.to_path_buf()));
+        }
+        let parent = match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        if path.to_string_lossy().ends_with('/') || !parent.is_dir() {
+            return Err(format!(
+                "OutputNotFound: directory {} does not exist",
+                path.display()
+            )
+            .into());
+        }
+        Ok(DownloadTarget::File(path.to_path_buf()))
+    }
+}
+
+/// Result of publishing a hidden package version.
+#[derive(Debug, Serialize)]
+pub struct PackagePublishOutput {
+    pub package_name: String,
+    pub package_version: String,
+    pub files: Vec<String>,
+}
+
+/// A package or package file referenced by a GitLab url.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PackageUrl {
+    /// `<host>/<project>/-/packages/<id>`, as shown in the web UI
+    Package { project: String, package_id: u64 },
+    /// `<host>/api/v4/projects/<project>/packages/generic/<name>/<version>/<file>`
+    GenericFile {
+        project: String,
+        package_name: String,
+        package_version: String,
+        file_name: String,
+    },
+}
+
+impl PackageUrl {
+    /// Parses `url` as a package url on `host`, e.g. `https://gitlab.com`.
+    /// Project paths are returned decoded, e.g. `owner/project`.
+    pub fn parse(url: &str, host: &str) -> Result<Self, String> {
+        let invalid = || {
+            format!(
+                "InvalidPackageUrl: {} is not a package url on {}",
+                url, host
+            )
+        };
+        let path = url
+            .strip_prefix(host.trim_end_matches('/'))
+            .filter(|path| path.starts_with('/'))
+            .ok_or_else(invalid)?;
+        let path = path.split(['?', '#']).next().unwrap_or_default();
+        let path = path.trim_end_matches('/');
+        let decode = |s: &str| {
+            urlencoding::decode(s)
+                .map(|s| s.into_owned())
+                .map_err(|_| invalid())
+        };
+        if let Some(api_path) = path.strip_prefix("/api/v4/projects/") {
+            let segments: Vec<&str> = api_path.split('/').collect();
+            return match segments[..] {
+                [project, "packages", "generic", name, version, file] => {
+                    Ok(PackageUrl::GenericFile {
+                        project: decode(project)?,
+                        package_name: decode(name)?,
+                        package_version: decode(version)?,
+                        file_name: decode(file)?,
+                    })
+                }
+                _ => Err(invalid()),
+            };
+        }
+        let (project, package_id) = path.split_once("/-/packages/").ok_or_else(invalid)?;
+        Ok(PackageUrl::Package {
+            project: decode(project.trim_start_matches('/'))?,
+            package_id: package_id.parse().map_err(|_| invalid())?,
+        })
+    }
+}
+
+/// Values of the placeholders in an output template.
+#[derive(Debug, Clone, Copy)]
+pub struct OutputTemplateValues<'a> {
+    pub project: &'a str,
+    pub name: &'a str,
+    pub version: &'a str,
+    pub file: &'a str,
+    pub sha256: &'a str,
+}
+
+/// Renders an output path template such as `{name}/{version}/{file}`.
+///
+/// Known placeholders are `{project}`, `{name}`, `{version}`, `{file}` and `{sha256}`,
+/// anything else in braces is an error.
+pub fn render_output_template(
+    template: &str,
+    values: &OutputTemplateValues,
+) -> Result<PathBuf, String> {
+    let mut rendered = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        rendered.push_str(&rest[..start]);
+        let end = rest[start..]
+            .find('}')
+            .ok_or_else(|| format!("InvalidTemplate: unclosed {{ in {}", template))?;
+        let placeholder = &rest[start + 1..start + end];
+        rendered.push_str(match placeholder {
+            "project" => values.project,
+            "name" => values.name,
+            "version" => values.version,
+            "file" => values.file,
+            "sha256" => values.sha256,
+            _ => {
+                return Err(format!(
+                    "InvalidTemplate: unknown placeholder {{{}}} in {}",
+                    placeholder, template
+                ));
+            }
+        });
+        rest = &rest[start + end + 1..];
+    }
+    rendered.push_str(rest);
+    Ok(PathBuf::from(rendered))
+}
+
+/// Result of downloading the files of a package version.
+#[derive(Debug, Serialize)]
+pub struct PackageDownloadOutput {
+    pub package_name: String,
+    /// The concrete version that was downloaded
+    pub package_version: String,
+    pub files: Vec<String>,
+    /// Files unpacked from archives, when extracting
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub extracted: Option<Vec<String>>,
+}
+
 impl GenericPackageOp {
     pub fn new(project_id: impl ToString, package_name: &str, file_name: &str) -> Self {
         Self {
@@ -358,6 +968,43 @@ impl GenericPackageOp {
             package_name: package_name.to_string(),
             file_name: file_name.to_string(),
             package_version: None,
+            version_strategy: VersionStrategy::default(),
+            package_type: None,
+            extract: false,
+            strip_components: 0,
+            output_template: None,
+            no_clobber: false,
+            instance: None,
+            status: None,
+            signing_key: None,
+            public_key: None,
+            require_signature: false,
+        }
+    }
+
+    /// Creates the operation for a package web url or a generic package file url
+    /// on `GITLAB_HOST`. For a file url, `file_name` is set to the file.
+    pub async fn from_url(url: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        match PackageUrl::parse(url, gitlab_host())? {
+            PackageUrl::Package {
+                project,
+                package_id,
+            } => {
+                let project_id = urlencoding::encode(&project).to_string();
+                let package = ProjectPackageListOp::new(&project_id)
+                    .package_by_id(package_id)
+                    .await?;
+                Ok(Self::new(project_id, &package.name, "").package_version(Some(package.version)))
+            }
+            PackageUrl::GenericFile {
+                project,
+                package_name,
+                package_version,
+                file_name,
+            } => Ok(
+                Self::new(urlencoding::encode(&project), &package_name, &file_name)
+                    .package_version(Some(package_version)),
+            ),
         }
     }
 
@@ -370,60 +1017,257 @@ impl GenericPackageOp {
         self.package_version = pv;
         self
     }
+    pub fn version_strategy(mut self, version_strategy: VersionStrategy) -> Self {
+        self.version_strategy = version_strategy;
+        self
+    }
+    pub fn package_type(mut self, package_type: Option<PackageType>) -> Self {
+        self.package_type = package_type;
+        self
+    }
+    pub fn extract(mut self, extract: bool) -> Self {
+        self.extract = extract;
+        self
+    }
+    pub fn strip_components(mut self, strip_components: usize) -> Self {
+        self.strip_components = strip_components;
+        self
+    }
+    pub fn output_template(mut self, output_template: Option<String>) -> Self {
+        self.output_template = output_template;
+        self
+    }
+    pub fn no_clobber(mut self, no_clobber: bool) -> Self {
+        self.no_clobber = no_clobber;
+        self
+    }
+    pub fn instance(mut self, instance: Option<GitlabInstance>) -> Self {
+        self.instance = instance;
+        self
+    }
+    pub fn status(mut self, status: Option<UploadStatus>) -> Self {
+        self.status = status;
+        self
+    }
+    pub fn signing_key(mut self, signing_key: Option<SecretKey>) -> Self {
+        self.signing_key = signing_key;
+        self
+    }
+    pub fn public_key(mut self, public_key: Option<PublicKey>) -> Self {
+        self.public_key = public_key;
+        self
+    }
+    pub fn require_signature(mut self, require_signature: bool) -> Self {
+        self.require_signature = require_signature;
+        self
+    }
     pub fn file_name(mut self, file_name: &str) -> Self {
         self.file_name = file_name.to_string();
         self
     }
 
+    /// Downloads the files of a package version matching `pattern` or `filename`.
+    ///
+    /// `output` is a directory, a file path (exactly one file must match) or `-` for stdout.
     pub async fn download_files(
         self,
-        output_dir: PathBuf,
+        output: PathBuf,
         pattern: Option<String>,
         filename: Option<String>,
-    ) -> Result<(), Box<dyn std::error::Error>> {
+    ) -> Result<PackageDownloadOutput, Box<dyn std::error::Error>> {
+        let target = DownloadTarget::from_path(&output)?;
+        if self.extract && !matches!(target, DownloadTarget::Dir(_)) {
+            return Err("Extracting requires an output directory".into());
+        }
+        if self.require_signature && self.public_key.is_none() {
+            return Err("Requiring signatures needs a trusted public key".into());
+        }
+        if self.output_template.is_some() && !matches!(target, DownloadTarget::Dir(_)) {
+            return Err("An output template requires an output directory".into());
+        }
         let pattern = pattern.map(|x| Regex::new(&x).unwrap());
         let filter = make_filter(pattern, filename);
-        let mut project_packages_list_op = ProjectPackageListOp::new(&self.project_id)
-            .package_name(Some(self.package_name.clone()));
-        let package_files = if let Some(version) = self.package_version.as_ref() {
-            project_packages_list_op
-                .package_files_by_version(version)
-                .await?
-        } else {
-            project_packages_list_op
-                .package_files_latest_version()
-                .await?
-        };
+        let package = self.resolve_package().await?;
+        let project_packages_list_op = ProjectPackageListOp::new(&self.project_id);
+        let all_files = latest_uploads(project_packages_list_op.package_files(&package).await?);
+        let package_files: Vec<&PackageFileInfo> =
+            all_files.iter().filter(|f| filter.filter(f)).collect();
+        if package_files.is_empty() {
+            return Err(format!(
+                "NoFilesMatched: {}@{} has no matching files",
+                &package.name, &package.version
+            )
+            .into());
+        }
+        if package_files.len() > 1 && !matches!(target, DownloadTarget::Dir(_)) {
+            return Err(format!(
+                "AmbiguousFile: {} files match, but only one can be written to {}",
+                package_files.len(),
+                output.display()
+            )
+            .into());
+        }
 
         let mut outputs = vec![];
+        let mut extracted = vec![];
         for package_file in &package_files {
-            if !filter.filter(package_file) {
-                continue;
-            }
-            let package_file_path = format!(
-                "/projects/{}/packages/generic/{}/{}/{}",
-                self.project_id,
+            let url = generic_package_file_url(
+                &self.project_id,
                 package_file.name.as_ref().unwrap(),
                 package_file.version.as_ref().unwrap(),
-                package_file.file_name.as_str()
-            );
-            let url = gitlab_api_url(&package_file_path)?;
-            let output_file = if output_dir.is_dir() {
-                output_dir.join(&package_file.file_name)
-            } else {
-                eprintln!("Warning: ouput_dir is not a directory, use /tmp as fallback");
-                PathBuf::from("/tmp").join(&package_file.file_name)
+                &package_file.file_name,
+            )?;
+            let content = download_bytes(url).await?;
+            self.verify_signature(&package, &all_files, package_file, &content)
+                .await?;
+            let output_file = match &target {
+                DownloadTarget::Stdout => {
+                    std::io::stdout().write_all(&content)?;
+                    eprintln!(
+                        "Wrote {} ({}) to stdout",
+                        &package_file.file_name, &package.version
+                    );
+                    return Ok(PackageDownloadOutput {
+                        package_name: package.name,
+                        package_version: package.version,
+                        files: vec!["-".to_string()],
+                        extracted: None,
+                    });
+                }
+                DownloadTarget::Dir(output_dir) => match &self.output_template {
+                    Some(template) => output_dir.join(render_output_template(
+                        template,
+                        &OutputTemplateValues {
+                            project: &urlencoding::decode(&self.project_id)?,
+                            name: &package.name,
+                            version: &package.version,
+                            file: &package_file.file_name,
+                            sha256: &hex::encode(Sha256::digest(&content)),
+                        },
+                    )?),
+                    None => output_dir.join(&package_file.file_name),
+                },
+                DownloadTarget::File(output_file) => output_file.clone(),
             };
             let output_str = output_file.as_path().to_str().unwrap().to_string();
-            let _ = download_file(url, &output_file).await?;
-            outputs.push(output_str);
+            if self.no_clobber && output_file.exists() {
+                if file_sha256(&output_file)? != hex::encode(Sha256::digest(&content)) {
+                    return Err(format!(
+                        "TargetExists: {} exists with different content",
+                        output_file.display()
+                    )
+                    .into());
+                }
+                eprintln!("{} is up to date", output_file.display());
+            } else {
+                if let Some(parent) = output_file.parent() {
+                    std::fs::create_dir_all(parent)?;
+                }
+                File::create(&output_file)?.write_all(&content)?;
+            }
+            let archive_kind = ArchiveKind::from_file_name(&package_file.file_name);
+            match archive_kind.filter(|_| self.extract) {
+                Some(kind) => {
+                    let output_dir = output_file.parent().unwrap_or(Path::new("."));
+                    let files =
+                        archive::extract(&output_file, kind, output_dir, self.strip_components)?;
+                    std::fs::remove_file(&output_file)?;
+                    extracted.extend(files.iter().map(|f| f.to_string_lossy().to_string()));
+                }
+                None => outputs.push(output_str),
+            }
         }
+        let output = PackageDownloadOutput {
+            package_name: package.name,
+            package_version: package.version,
+            files: outputs,
+            extracted: self.extract.then_some(extracted),
+        };
         let msg = PrintOutput {
             status: "ok".to_string(),
-            output: outputs,
+            output: &output,
         };
         let msg = serde_json::to_string_pretty(&msg)?;
         println!("{}", msg);
+        Ok(output)
+    }
+
+    /// Looks up the package version to work on, `package_version` or the one
+    /// picked by `version_strategy`, among versions with `status` if set.
+    /// Only generic packages are accepted.
+    pub async fn resolve_package(&self) -> Result<PackageInfo, Box<dyn std::error::Error>> {
+        let project_packages_list_op = ProjectPackageListOp::new(&self.project_id)
+            .package_name(Some(self.package_name.clone()))
+            .package_type(self.package_type.clone())
+            .status(self.status.map(PackageStatus::from));
+        let package = if let Some(version) = self.package_version.as_ref() {
+            let packages = project_packages_list_op
+                .clone()
+                .package_version(Some(version.clone()))
+                .list_all()
+                .await?;
+            select_package(
+                packages,
+                Some(&self.package_name),
+                &VersionStrategy::CreatedAt,
+            )
+            .ok_or_else(|| format!("PackageNotFound: {}@{}", self.package_name, version))?
+        } else {
+            project_packages_list_op
+                .resolve(&self.version_strategy)
+                .await?
+        };
+        eprintln!(
+            "Resolved {} ({}) to version {}",
+            &package.name, &self.version_strategy, &package.version
+        );
+        if let Some(package_type) = package
+            .package_type
+            .as_ref()
+            .filter(|t| **t != PackageType::Generic)
+        {
+            return Err(format!(
+                "Only generic packages can be downloaded, {} is of type {}",
+                &package.name, package_type
+            )
+            .into());
+        }
+        Ok(package)
+    }
+
+    /// Verifies `content` against its `.minisig` sidecar among `files`,
+    /// if a trusted public key is set.
+    async fn verify_signature(
+        &self,
+        package: &PackageInfo,
+        files: &[PackageFileInfo],
+        file: &PackageFileInfo,
+        content: &[u8],
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let Some(public_key) = &self.public_key else {
+            return Ok(());
+        };
+        if file.file_name.ends_with(SIGNATURE_SUFFIX) {
+            return Ok(());
+        }
+        let signature_name = format!("{}{}", &file.file_name, SIGNATURE_SUFFIX);
+        if !files.iter().any(|f| f.file_name == signature_name) {
+            if self.require_signature {
+                return Err(format!("Unsigned: {} has no signature", &file.file_name).into());
+            }
+            eprintln!("Warning: {} is not signed", &file.file_name);
+            return Ok(());
+        }
+        let url = generic_package_file_url(
+            &self.project_id,
+            &package.name,
+            &package.version,
+            &signature_name,
+        )?;
+        let signature = String::from_utf8(download_bytes(url).await?)?;
+        signing::verify(public_key, content, &signature)
+            .map_err(|e| format!("BadSignature: {}: {}", &file.file_name, e))?;
+        eprintln!("Verified signature of {}", &file.file_name);
         Ok(())
     }
 
@@ -439,21 +1283,189 @@ impl GenericPackageOp {
         file_name: &str,
         file_path: PathBuf,
     ) -> Result<(), Box<dyn std::error::Error>> {
-        let url_path = format!(
-            "/projects/{}/packages/generic/{}/{}/{}",
-            self.project_id, self.package_name, package_version, file_name
-        );
-        let url = gitlab_api_url(&url_path)?;
         let file = tokio::fs::read(file_path).await?;
+        self.upload_signed_bytes(package_version, file_name, file)
+            .await
+    }
+
+    /// Uploads a `SHA256SUMS` file listing every file of the package version,
+    /// with the sha256 reported by GitLab.
+    pub async fn upload_sha256sums(
+        &self,
+        package_version: &str,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let files = self.version_files(package_version).await?;
+        self.upload_sha256sums_of(package_version, files).await
+    }
+
+    /// Makes a hidden package version visible.
+    ///
+    /// GitLab only changes the status of a generic package when a file is uploaded,
+    /// so this uploads `final_file` and/or the `SHA256SUMS` of the version with status `default`.
+    pub async fn publish(
+        &self,
+        package_version: &str,
+        final_file: Option<PathBuf>,
+        sha256sums: bool,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        if final_file.is_none() && !sha256sums {
+            return Err(
+                "Nothing to publish with: GitLab only changes the status when a file is uploaded, \
+                 give a final file or SHA256SUMS"
+                    .into(),
+            );
+        }
+        let list_op = ProjectPackageListOp::new(&self.project_id)
+            .package_name(Some(self.package_name.clone()))
+            .package_version(Some(package_version.to_string()))
+            .status(Some(PackageStatus::Hidden));
+        let package = select_package(
+            list_op.list_all().await?,
+            Some(&self.package_name),
+            &VersionStrategy::CreatedAt,
+        )
+        .ok_or_else(|| {
+            format!(
+                "PackageNotFound: no hidden version {} of {}",
+                package_version, &self.package_name
+            )
+        })?;
+        let mut file_names = list_op
+            .package_files(&package)
+            .await?
+            .into_iter()
+            .map(|f| f.file_name)
+            .filter(|f| !is_sha256sums_file(f))
+            .collect::<BTreeSet<_>>();
+        let published = self.clone().status(Some(UploadStatus::Default));
+        if let Some(path) = final_file {
+            let file_name = path
+                .file_name()
+                .ok_or("File name not found")?
+                .to_string_lossy()
+                .to_string();
+            published
+                .upload_package_file(package_version, &file_name, path)
+                .await?;
+            file_names.insert(file_name);
+        } else if file_names.is_empty() {
+            return Err(format!(
+                "EmptyPackage: {}@{} has no files to publish",
+                &self.package_name, package_version
+            )
+            .into());
+        }
+        if sha256sums {
+            let files = list_op.package_files(&package).await?;
+            published
+                .upload_sha256sums_of(package_version, files)
+                .await?;
+        }
+        let msg = PrintOutput {
+            status: "ok".to_string(),
+            output: PackagePublishOutput {
+                package_name: self.package_name.clone(),
+                package_version: package_version.to_string(),
+                files: file_names.into_iter().collect(),
+            },
+        };
+        println!("{}", serde_json::to_string_pretty(&msg)?);
+        Ok(())
+    }
+
+    /// Files of a package version, hidden versions are only found with their `status`.
+    async fn version_files(
+        &self,
+        package_version: &str,
+    ) -> Result<Vec<PackageFileInfo>, Box<dyn std::error::Error>> {
+        let package = self
+            .clone()
+            .package_version(Some(package_version.to_string()))
+            .resolve_package()
+            .await?;
+        ProjectPackageListOp::new(&self.project_id)
+            .package_files(&package)
+            .await
+    }
+
+    async fn upload_sha256sums_of(
+        &self,
+        package_version: &str,
+        files: Vec<PackageFileInfo>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let mut sums = BTreeMap::new();
+        for file in latest_uploads(files) {
+            if is_sha256sums_file(&file.file_name) {
+                continue;
+            }
+            let sha256 = file
+                .file_sha256
+                .ok_or(format!("No sha256 reported for {}", &file.file_name))?;
+            sums.insert(file.file_name, sha256);
+        }
+        self.upload_signed_bytes(
+            package_version,
+            SHA256SUMS,
+            format_sha256sums(&sums).into_bytes(),
+        )
+        .await
+    }
+
+    /// Uploads the content of a package file, followed by its signature if `signing_key` is set.
+    pub async fn upload_signed_bytes(
+        &self,
+        package_version: &str,
+        file_name: &str,
+        file: Vec<u8>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let signature = self
+            .signing_key
+            .as_ref()
+            .map(|key| key.sign(&file, file_name));
+        let content = self
+            .upload_package_body(package_version, file_name, file)
+            .await?;
+        println!("{}", content);
+        if let Some(signature) = signature {
+            let signature_name = format!("{}{}", file_name, SIGNATURE_SUFFIX);
+            let content = self
+                .upload_package_body(package_version, &signature_name, signature.into_bytes())
+                .await?;
+            println!("{}", content);
+        }
+        Ok(())
+    }
+
+    /// Uploads the content of a package file, to `instance` if set.
+    ///
+    /// Returns the uploaded package file as reported by GitLab,
+    /// which includes its `file_sha256`.
+    pub async fn upload_package_body(
+        &self,
+        package_version: &str,
+        file_name: &str,
+        content: impl Into<Body>,
+    ) -> Result<serde_json::Value, Box<dyn std::error::Error>> {
+        let path = generic_package_file_path(
+            &self.project_id,
+            &self.package_name,
+            package_version,
+            file_name,
+        );
+        let (mut url, token) = self.api_url(&path)?;
+        url.query_pairs_mut().append_pair("select", "package_file");
+        if let Some(status) = self.status {
+            url.query_pairs_mut()
+                .append_pair("status", PackageStatus::from(status).as_str());
+        }
         let response = httpclient()
             .put(url)
-            .header("Private-Token", gitlab_token())
-            .body(file)
+            .header("Private-Token", token)
+            .body(content)
             .send()
             .await?;
         let status = response.status();
         let content = response.text().await?;
-        dbg!(&content);
         if status != 201 {
             return Err(format!(
                 "Upload failed with status: {}, and message: {}",
@@ -461,9 +1473,115 @@ impl GenericPackageOp {
             )
             .into());
         }
-        println!("{}", content);
+        Ok(serde_json::from_str(&content)?)
+    }
+
+    /// Deletes a package file as returned by [`Self::upload_package_body`], from `instance` if set.
+    pub async fn delete_uploaded_file(
+        &self,
+        uploaded: &serde_json::Value,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let (Some(package_id), Some(file_id)) =
+            (uploaded["package_id"].as_u64(), uploaded["id"].as_u64())
+        else {
+            return Err(format!("Not an uploaded package file: {}", uploaded).into());
+        };
+        let (url, token) = self.api_url(&format!(
+            "/projects/{}/packages/{}/package_files/{}",
+            &self.project_id, package_id, file_id
+        ))?;
+        let response = httpclient()
+            .delete(url)
+            .header("Private-Token", token)
+            .send()
+            .await?;
+        let status = response.status();
+        if !status.is_success() {
+            return Err(format!(
+                "DeletePackageErr: {} failed with status: {}, and message: {}",
+                file_id,
+                status,
+                response.text().await?
+            )
+            .into());
+        }
         Ok(())
     }
+
+    /// Api url of `path` and the token to use, on `instance` if set.
+    fn api_url(&self, path: &str) -> Result<(Url, &str), Box<dyn std::error::Error>> {
+        match &self.instance {
+            Some(instance) => Ok((instance.api_url(path)?, instance.token.as_str())),
+            None => Ok((gitlab_api_url(path)?, gitlab_token().as_str())),
+        }
+    }
+}
+
+/// Keeps only the last upload of every file name, ordered by file name.
+/// The generic registry allows uploading the same file name to a version more than once.
+pub fn latest_uploads(files: Vec<PackageFileInfo>) -> Vec<PackageFileInfo> {
+    let mut by_name = BTreeMap::new();
+    for file in files {
+        match by_name.get(&file.file_name) {
+            Some(PackageFileInfo { id, .. }) if *id > file.id => {}
+            _ => {
+                by_name.insert(file.file_name.clone(), file);
+            }
+        }
+    }
+    by_name.into_values().collect()
+}
+
+/// Api path of a file in the generic package registry.
+///
+/// `project_id` must already be URL-encoded if it is a path.
+pub fn generic_package_file_path(
+    project_id: &str,
+    package_name: &str,
+    package_version: &str,
+    file_name: &str,
+) -> String {
+    format!(
+        "/projects/{}/packages/generic/{}/{}/{}",
+        project_id, package_name, package_version, file_name
+    )
+}
+
+/// Url of a file in the generic package registry.
+///
+/// `project_id` must already be URL-encoded if it is a path.
+pub fn generic_package_file_url(
+    project_id: &str,
+    package_name: &str,
+    package_version: &str,
+    file_name: &str,
+) -> Result<Url, Box<dyn std::error::Error>> {
+    gitlab_api_url(&generic_package_file_path(
+        project_id,
+        package_name,
+        package_version,
+        file_name,
+    ))
+}
+
+/// Splits `name@version` into name and version, keeping the `@` of a scope.
+pub fn split_package_spec(spec: &str) -> Result<(&str, &str), String> {
+    match spec.rfind('@') {
+        Some(i) if i > 0 => Ok((&spec[..i], &spec[i + 1..])),
+        _ => Err(format!(
+            "InvalidPackageSpec: expected <name>@<version>, got {}",
+            spec
+        )),
+    }
+}
+
+/// Hex encoded sha256 of a local file.
+pub fn file_sha256<P>(path: P) -> Result<String, Box<dyn std::error::Error>>
+where
+    P: AsRef<Path>,
+{
+    let content = std::fs::read(path)?;
+    Ok(hex::encode(Sha256::digest(&content)))
 }
 
 /// Downloads a file from a given URL.
@@ -480,11 +1598,25 @@ pub async fn download_file<P>(url: Url, output_file: P) -> Result<(), Box<dyn st
 where
     P: AsRef<Path>,
 {
-    let response = httpclient()
-        .get(url)
-        .header("Private-Token", gitlab_token())
-        .send()
-        .await?;
+    let content = download_bytes(url).await?;
+    let mut file = File::create(output_file)?;
+    file.write_all(&content)?;
+    Ok(())
+}
+
+/// Downloads a file from a given URL into memory.
+pub async fn download_bytes(url: Url) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    fetch_bytes(
+        httpclient()
+            .get(url)
+            .header("Private-Token", gitlab_token()),
+    )
+    .await
+}
+
+/// Sends a download request, failing on any status but 200.
+pub async fn fetch_bytes(request: RequestBuilder) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let response = request.send().await?;
     let status = response.status();
     let content = response.bytes().await?;
     if status != 200 {
@@ -494,9 +1626,7 @@ where
         )
         .into());
     }
-    let mut file = File::create(output_file)?;
-    file.write_all(&content)?;
-    Ok(())
+    Ok(content.to_vec())
 }
 
 /// Helper function to delete package related info.
@@ -569,3 +1699,243 @@ where
     let json_bytes = response.bytes().await?.to_vec();
     Ok(json_bytes)
 }
+
+#[cfg(test)]
+mod packages_tests {
+    use super::super::test_gitlab;
+    use super::*;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+
+    fn package(version: &str, created_at: &str) -> PackageInfo {
+        PackageInfo {
+            id: 0,
+            name: "glabu".to_string(),
+            version: version.to_string(),
+            tags: vec![],
+            created_at: Some(created_at.to_string()),
+            last_downloaded_at: None,
+            package_type: None,
+            status: None,
+            project_id: None,
+            project_path: None,
+        }
+    }
+
+    fn packages() -> Vec<PackageInfo> {
+        vec![
+            package("1.4.2", "2025-01-01T00:00:00Z"),
+            package("2.0.0", "2025-02-01T00:00:00Z"),
+            package("abc123", "2025-02-15T00:00:00Z"),
+            package("1.4.3", "2025-03-01T00:00:00Z"),
+            package("v2.0.1-rc.1", "2025-03-02T00:00:00Z"),
+        ]
+    }
+
+    fn selected(strategy: &str) -> Option<String> {
+        let strategy = strategy.parse::<VersionStrategy>().unwrap();
+        select_package(packages(), Some("glabu"), &strategy).map(|p| p.version)
+    }
+
+    #[test]
+    fn test_select_package() {
+        assert_eq!(selected("created-at").as_deref(), Some("v2.0.1-rc.1"));
+        assert_eq!(selected("semver-max").as_deref(), Some("v2.0.1-rc.1"));
+        assert_eq!(selected("^1.4").as_deref(), Some("1.4.3"));
+        assert_eq!(selected("~2.0").as_deref(), Some("2.0.0"));
+        assert_eq!(selected("^3"), None);
+    }
+
+    #[tokio::test]
+    async fn test_list_pages() {
+        let items: Vec<u64> = (1..=5).collect();
+        let pages = list_pages(2, |page| {
+            let chunk = items
+                .chunks(2)
+                .nth(page as usize - 1)
+                .unwrap_or(&[])
+                .to_vec();
+            async move { Ok(chunk) }
+        })
+        .await
+        .unwrap();
+        assert_eq!(pages, items);
+    }
+
+    #[test]
+    fn test_select_package_exact_name() {
+        let mut packages = packages();
+        let mut other = package("9.9.9", "2025-04-01T00:00:00Z");
+        other.name = "glabu-extra".to_string();
+        packages.push(other);
+        let newest = select_package(packages, Some("glabu"), &VersionStrategy::CreatedAt);
+        assert_eq!(newest.unwrap().version, "v2.0.1-rc.1");
+    }
+
+    #[test]
+    fn test_parse_version() {
+        assert_eq!(parse_version("v1.2.3"), Some(Version::new(1, 2, 3)));
+        assert_eq!(parse_version("1.4"), Some(Version::new(1, 4, 0)));
+        assert_eq!(parse_version("abc123"), None);
+    }
+
+    #[test]
+    fn test_package_url() {
+        let host = "https://gitlab.com";
+        assert_eq!(
+            PackageUrl::parse(
+                "https://gitlab.com/puterize/prebuilt/-/packages/12345/",
+                host
+            ),
+            Ok(PackageUrl::Package {
+                project: "puterize/prebuilt".to_string(),
+                package_id: 12345
+            })
+        );
+        assert_eq!(
+            PackageUrl::parse(
+                "https://gitlab.com/api/v4/projects/puterize%2Fprebuilt/packages/generic/glabu/abc123/glabu-x86_64?x=1",
+                host
+            ),
+            Ok(PackageUrl::GenericFile {
+                project: "puterize/prebuilt".to_string(),
+                package_name: "glabu".to_string(),
+                package_version: "abc123".to_string(),
+                file_name: "glabu-x86_64".to_string(),
+            })
+        );
+        assert!(PackageUrl::parse("https://example.com/a/b/-/packages/1", host).is_err());
+        assert!(PackageUrl::parse("https://gitlab.com.evil/a/-/packages/1", host).is_err());
+        assert!(PackageUrl::parse("https://gitlab.com/a/b/-/packages/x", host).is_err());
+    }
+
+    #[test]
+    fn test_render_output_template() {
+        let values = OutputTemplateValues {
+            project: "puterize/prebuilt",
+            name: "glabu",
+            version: "1.4.0",
+            file: "glabu-x86_64",
+            sha256: "abc",
+        };
+        let render = |t: &str| render_output_template(t, &values);
+        assert_eq!(
+            render("{project}/{name}/{version}/{file}"),
+            Ok(PathBuf::from("puterize/prebuilt/glabu/1.4.0/glabu-x86_64"))
+        );
+        assert_eq!(
+            render("{name}-{sha256}.bin"),
+            Ok(PathBuf::from("glabu-abc.bin"))
+        );
+        assert!(render("{arch}/{file}").is_err());
+        assert!(render("{name").is_err());
+    }
+
+    #[test]
+    fn test_package_type() {
+        let t: PackageType = serde_json::from_str("\"ml_model\"").unwrap();
+        assert_eq!(t, PackageType::MlModel);
+        let t: PackageType = serde_json::from_str("\"cocoapods\"").unwrap();
+        assert_eq!(t, PackageType::Unknown("cocoapods".to_string()));
+        assert_eq!(serde_json::to_string(&t).unwrap(), "\"cocoapods\"");
+        assert_eq!("terraform-module".parse(), Ok(PackageType::TerraformModule));
+        let err = "gneric".parse::<PackageType>().unwrap_err();
+        assert!(err.contains("gneric") && err.contains("generic, golang"));
+    }
+
+    #[test]
+    fn test_split_package_spec() {
+        assert_eq!(
+            split_package_spec("@puterize/ui@1.4.0"),
+            Ok(("@puterize/ui", "1.4.0"))
+        );
+        assert_eq!(split_package_spec("ui@1.4.0"), Ok(("ui", "1.4.0")));
+        assert!(split_package_spec("@puterize/ui").is_err());
+    }
+
+    /// Answers 304 when `If-None-Match` has the current ETag, the list otherwise.
+    fn stand_in_registry() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut if_none_match = None;
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    if line.trim_end().is_empty() {
+                        break;
+                    }
+                    if let Some((k, v)) = line.trim_end().split_once(": ")
+                        && k.eq_ignore_ascii_case("if-none-match")
+                    {
+                        if_none_match = Some(v.to_string());
+                    }
+                }
+                let response = match if_none_match.as_deref() {
+                    Some("\"v2\"") => "HTTP/1.1 304 Not Modified\r\nConnection: close\r\n\r\n",
+                    _ => {
+                        "HTTP/1.1 200 OK\r\nETag: \"v2\"\r\nContent-Length: 7\r\nConnection: close\r\n\r\n[\"1.1\"]"
+                    }
+                };
+                stream.write_all(response.as_bytes()).unwrap();
+            }
+        });
+        base
+    }
+
+    #[tokio::test]
+    async fn test_get_if_changed() {
+        let base = stand_in_registry();
+        let client = reqwest::Client::new();
+        let (versions, etag) = get_if_changed::<Vec<String>>(client.get(&base), Some("\"v1\""))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(versions, ["1.1"]);
+        assert_eq!(etag.as_deref(), Some("\"v2\""));
+        let unchanged = get_if_changed::<Vec<String>>(client.get(&base), etag.as_deref())
+            .await
+            .unwrap();
+        assert!(unchanged.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_upload_sha256sums_exact_name() {
+        // GitLab matches names fuzzily, `glabu` also finds the newer `glabu-cli`
+        test_gitlab::respond(
+            "GET",
+            "/projects/sums%2Fprebuilt/packages",
+            200,
+            r#"[
+                {"id": 1, "name": "glabu", "version": "1.0.0", "tags": [],
+                 "created_at": "2025-03-01T10:00:00.000Z", "package_type": "generic"},
+                {"id": 2, "name": "glabu-cli", "version": "1.0.0", "tags": [],
+                 "created_at": "2025-03-02T10:00:00.000Z", "package_type": "generic"}
+            ]"#,
+        );
+        for (id, file_name) in [(1, "glabu-x86_64"), (2, "glabu-cli-x86_64")] {
+            test_gitlab::respond(
+                "GET",
+                &format!("/projects/sums%2Fprebuilt/packages/{}/package_files", id),
+                200,
+                format!(
+                    r#"[{{"id": {id}, "package_id": {id}, "created_at": "2025-03-01T10:00:00.000Z",
+                         "file_name": "{file_name}", "file_sha256": "sha-of-{id}"}}]"#
+                ),
+            );
+        }
+        let sums_path = "/projects/sums%2Fprebuilt/packages/generic/glabu/1.0.0/SHA256SUMS";
+        test_gitlab::respond("PUT", sums_path, 201, "{}");
+
+        GenericPackageOp::new("sums%2Fprebuilt", "glabu", "")
+            .upload_sha256sums("1.0.0")
+            .await
+            .unwrap();
+        let uploads = test_gitlab::requests(sums_path);
+        assert_eq!(uploads.len(), 1);
+        assert_eq!(uploads[0].body, b"sha-of-1  glabu-x86_64\n");
+    }
+}
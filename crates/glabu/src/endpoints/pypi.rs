@@ -0,0 +1,398 @@
+//! Publish to and download from the PyPI registry of a GitLab project.
+//!
+//! Uploads use the same multipart form as twine, with the metadata read from the
+//! wheel (`*.dist-info/METADATA`) or sdist (`PKG-INFO`). Downloads go through the
+//! simple index (PEP 503).
+//! See gitlab api doc: https://docs.gitlab.com/api/packages/pypi/
+
+use super::checksums::verify_sha256;
+use super::packages::{PackageDownloadOutput, PackageType, ProjectPackageListOp, VersionStrategy};
+use super::setup::{gitlab_api_url, httpclient, with_basic_auth};
+use crate::archive::{self, ArchiveKind};
+use crate::endpoints::PrintOutput;
+use crate::models::PackageInfo;
+use regex::Regex;
+use reqwest::Url;
+use reqwest::multipart::{Form, Part};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+
+/// Core metadata of a Python distribution.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct PythonMetadata {
+    pub metadata_version: String,
+    pub name: String,
+    pub version: String,
+    pub summary: Option<String>,
+    pub requires_python: Option<String>,
+}
+
+impl PythonMetadata {
+    /// Parses the email-header style `METADATA`/`PKG-INFO` content, the body is ignored.
+    pub fn parse(content: &str) -> Result<Self, String> {
+        let mut metadata = PythonMetadata::default();
+        for line in content.lines() {
+            if line.is_empty() {
+                break;
+            }
+            let Some((key, value)) = line.split_once(':') else {
+                continue;
+            };
+            let value = value.trim().to_string();
+            match key.to_ascii_lowercase().as_str() {
+                "metadata-version" => metadata.metadata_version = value,
+                "name" => metadata.name = value,
+                "version" => metadata.version = value,
+                "summary" => metadata.summary = Some(value),
+                "requires-python" => metadata.requires_python = Some(value),
+                _ => {}
+            }
+        }
+        if metadata.name.is_empty() || metadata.version.is_empty() {
+            return Err("InvalidMetadata: Name and Version are required".to_string());
+        }
+        Ok(metadata)
+    }
+
+    /// Reads the metadata of a wheel (`.whl`) or sdist (`.tar.gz`, `.zip`).
+    pub fn from_distribution(path: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        let file_name = file_name(path)?;
+        let content = if file_name.ends_with(".whl") {
+            archive::read_entry(path, ArchiveKind::Zip, |entry| {
+                entry.ends_with("METADATA")
+                    && entry.components().count() == 2
+                    && entry
+                        .parent()
+                        .is_some_and(|dir| dir.to_string_lossy().ends_with(".dist-info"))
+            })?
+        } else {
+            let kind = ArchiveKind::from_file_name(&file_name)
+                .ok_or(format!("Not a wheel or sdist: {}", &file_name))?;
+            archive::read_entry(path, kind, |entry| {
+                entry.ends_with("PKG-INFO") && entry.components().count() == 2
+            })?
+        };
+        let content = content.ok_or(format!("No metadata found in {}", &file_name))?;
+        Ok(Self::parse(&String::from_utf8(content)?)?)
+    }
+}
+
+/// Normalizes a project name as in PEP 503, e.g. `My_Package` -> `my-package`.
+pub fn normalize_name(name: &str) -> String {
+    let separators = Regex::new(r"[-_.]+").unwrap();
+    separators.replace_all(name, "-").to_lowercase()
+}
+
+/// A file listed in the simple index.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IndexFile {
+    pub file_name: String,
+    pub url: Url,
+    pub sha256: Option<String>,
+}
+
+impl IndexFile {
+    /// Version part of a wheel or sdist file name of the project `name`.
+    pub fn version(&self, name: &str) -> Option<String> {
+        let name = normalize_name(name);
+        if let Some(stem) = self.file_name.strip_suffix(".whl") {
+            let mut parts = stem.split('-');
+            let dist = parts.next()?;
+            return (normalize_name(dist) == name)
+                .then(|| parts.next().map(String::from))
+                .flatten();
+        }
+        let stem = [".tar.gz", ".zip", ".tar.bz2"]
+            .iter()
+            .find_map(|ext| self.file_name.strip_suffix(ext))?;
+        let (dist, version) = stem.rsplit_once('-')?;
+        (normalize_name(dist) == name).then(|| version.to_string())
+    }
+}
+
+/// Parses the anchors of the simple index page at `index_url`, relative hrefs are
+/// resolved against it.
+pub fn parse_simple_index(html: &str, index_url: &Url) -> Result<Vec<IndexFile>, String> {
+    let anchor = Regex::new(r#"<a\s[^>]*href="([^"]+)"[^>]*>([^<]+)</a>"#).unwrap();
+    anchor
+        .captures_iter(html)
+        .map(|c| {
+            let href = c[1].replace("&amp;", "&");
+            let (url, fragment) = href.split_once('#').unwrap_or((&href, ""));
+            Ok(IndexFile {
+                file_name: c[2].trim().to_string(),
+                url: index_url
+                    .join(url)
+                    .map_err(|e| format!("InvalidIndex: link {} ({})", url, e))?,
+                sha256: fragment.strip_prefix("sha256=").map(String::from),
+            })
+        })
+        .collect()
+}
+
+/// Picks the version of the project `name` that best satisfies `strategy`,
+/// comparing names normalized as in PEP 503.
+pub fn select_version(
+    packages: Vec<PackageInfo>,
+    name: &str,
+    strategy: &VersionStrategy,
+) -> Option<PackageInfo> {
+    let name = normalize_name(name);
+    let candidates = packages
+        .into_iter()
+        .filter(|p| normalize_name(&p.name) == name);
+    strategy.select(candidates, |p| &p.version, |p| p.created_at.as_deref())
+}
+
+/// Result of uploading a distribution.
+#[derive(Debug, Serialize)]
+pub struct PypiUploadOutput {
+    pub file_name: String,
+    pub sha256: String,
+    #[serde(flatten)]
+    pub metadata: PythonMetadata,
+}
+
+#[derive(Debug, Clone)]
+pub struct PypiOp {
+    ///  Your project ID or URL-encoded path
+    pub project_id: String,
+}
+
+impl PypiOp {
+    pub fn new(project_id: &str) -> Self {
+        Self {
+            project_id: project_id.to_string(),
+        }
+    }
+
+    /// Uploads a wheel or sdist.
+    pub async fn upload(&self, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        let metadata = PythonMetadata::from_distribution(path)?;
+        let file_name = file_name(path)?;
+        let content = tokio::fs::read(path).await?;
+        let sha256 = hex::encode(Sha256::digest(&content));
+        let (filetype, pyversion) = if file_name.ends_with(".whl") {
+            // name-version(-build)?-python-abi-platform.whl
+            let tags: Vec<&str> = file_name.trim_end_matches(".whl").split('-').collect();
+            let pyversion = tags.iter().rev().nth(2).unwrap_or(&"py3").to_string();
+            ("bdist_wheel", pyversion)
+        } else {
+            ("sdist", "source".to_string())
+        };
+        let mut form = Form::new()
+            .text(":action", "file_upload")
+            .text("protocol_version", "1")
+            .text("metadata_version", metadata.metadata_version.clone())
+            .text("name", metadata.name.clone())
+            .text("version", metadata.version.clone())
+            .text("filetype", filetype)
+            .text("pyversion", pyversion)
+            .text("sha256_digest", sha256.clone());
+        if let Some(summary) = &metadata.summary {
+            form = form.text("summary", summary.clone());
+        }
+        if let Some(requires_python) = &metadata.requires_python {
+            form = form.text("requires_python", requires_python.clone());
+        }
+        form = form.part("content", Part::bytes(content).file_name(file_name.clone()));
+
+        let url = gitlab_api_url(&format!("/projects/{}/packages/pypi", &self.project_id))?;
+        eprintln!(
+            "Uploading {} ({} {})",
+            &file_name, &metadata.name, &metadata.version
+        );
+        let response = with_basic_auth(httpclient().post(url))
+            .multipart(form)
+            .send()
+            .await?;
+        let status = response.status();
+        if status != 201 {
+            return Err(format!(
+                "Upload failed with status: {}, and message: {}",
+                status,
+                response.text().await?
+            )
+            .into());
+        }
+        let msg = PrintOutput {
+            status: "ok".to_string(),
+            output: PypiUploadOutput {
+                file_name,
+                sha256,
+                metadata,
+            },
+        };
+        println!("{}", serde_json::to_string_pretty(&msg)?);
+        Ok(())
+    }
+
+    /// Lists the files of a project in the simple index.
+    pub async fn index(&self, name: &str) -> Result<Vec<IndexFile>, Box<dyn std::error::Error>> {
+        let url = gitlab_api_url(&format!(
+            "/projects/{}/packages/pypi/simple/{}",
+            &self.project_id,
+            normalize_name(name)
+        ))?;
+        let response = with_basic_auth(httpclient().get(url)).send().await?;
+        let status = response.status();
+        if status == 404 {
+            return Err(format!("PackageNotFound: {} is not in the PyPI registry", name).into());
+        }
+        if !status.is_success() {
+            return Err(format!("Reading the simple index failed with status: {}", status).into());
+        }
+        let index_url = response.url().clone();
+        Ok(parse_simple_index(&response.text().await?, &index_url)?)
+    }
+
+    /// Downloads the files of a version into `output_dir`, checking their sha256.
+    ///
+    /// Without a version, the version is resolved with `strategy` from the package list.
+    pub async fn download(
+        &self,
+        name: &str,
+        version: Option<String>,
+        strategy: &VersionStrategy,
+        pattern: Option<&str>,
+        output_dir: &Path,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let version = match version {
+            Some(version) => version,
+            None => {
+                // the fuzzy name filter of GitLab does not match across `-`, `_` and `.`
+                let packages = ProjectPackageListOp::new(&self.project_id)
+                    .package_type(Some(PackageType::Pypi))
+                    .list_all()
+                    .await?;
+                select_version(packages, name, strategy)
+                    .ok_or_else(|| {
+                        format!(
+                            "PackageNotFound: no version of {} satisfies {}",
+                            name, strategy
+                        )
+                    })?
+                    .version
+            }
+        };
+        let pattern = pattern.map(Regex::new).transpose()?;
+        let files: Vec<IndexFile> = self
+            .index(name)
+            .await?
+            .into_iter()
+            .filter(|f| f.version(name).as_deref() == Some(version.as_str()))
+            .filter(|f| pattern.as_ref().is_none_or(|p| p.is_match(&f.file_name)))
+            .collect();
+        if files.is_empty() {
+            return Err(format!(
+                "NoFilesMatched: {}=={} has no matching files",
+                name, version
+            )
+            .into());
+        }
+
+        std::fs::create_dir_all(output_dir)?;
+        let mut outputs = vec![];
+        for file in files {
+            let response = with_basic_auth(httpclient().get(file.url.clone()))
+                .send()
+                .await?;
+            let status = response.status();
+            if !status.is_success() {
+                return Err(format!("DownloadFileErr: {} ({})", &file.file_name, status).into());
+            }
+            let content = response.bytes().await?;
+            verify_sha256(&file.file_name, &content, file.sha256.as_deref())?;
+            let output_file: PathBuf = output_dir.join(&file.file_name);
+            tokio::fs::write(&output_file, &content).await?;
+            eprintln!("Downloaded {}", output_file.display());
+            outputs.push(output_file.to_string_lossy().to_string());
+        }
+        let msg = PrintOutput {
+            status: "ok".to_string(),
+            output: PackageDownloadOutput {
+                package_name: name.to_string(),
+                package_version: version,
+                files: outputs,
+                extracted: None,
+            },
+        };
+        println!("{}", serde_json::to_string_pretty(&msg)?);
+        Ok(())
+    }
+}
+
+fn file_name(path: &Path) -> Result<String, String> {
+    path.file_name()
+        .map(|s| s.to_string_lossy().to_string())
+        .ok_or(format!("File name not found: {}", path.display()))
+}
+
+#[cfg(test)]
+mod pypi_tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_metadata() {
+        let content = "Metadata-Version: 2.1\nName: My_Tool\nVersion: 1.4.0\n\
+            Summary: A tool\nRequires-Python: >=3.9\n\nName: body text\n";
+        let metadata = PythonMetadata::parse(content).unwrap();
+        assert_eq!(metadata.name, "My_Tool");
+        assert_eq!(metadata.version, "1.4.0");
+        assert_eq!(metadata.requires_python.as_deref(), Some(">=3.9"));
+        assert!(PythonMetadata::parse("Name: x\n").is_err());
+        assert_eq!(normalize_name("My_Tool.ext"), "my-tool-ext");
+    }
+
+    #[test]
+    fn test_parse_simple_index() {
+        let html = r#"<html><body>
+            <a href="https://gitlab.com/api/v4/projects/1/packages/pypi/files/ab/my_tool-1.4.0-py3-none-any.whl#sha256=ab" data-requires-python="&gt;=3.9">my_tool-1.4.0-py3-none-any.whl</a><br>
+            <a href="https://gitlab.com/api/v4/projects/1/packages/pypi/files/cd/my-tool-1.3.0.tar.gz#sha256=cd">my-tool-1.3.0.tar.gz</a><br>
+            <a href="../../files/ef/my_tool-1.2.0.tar.gz#sha256=ef">my_tool-1.2.0.tar.gz</a><br>
+            </body></html>"#;
+        let index_url =
+            Url::parse("https://gitlab.com/api/v4/projects/1/packages/pypi/simple/my-tool/")
+                .unwrap();
+        let files = parse_simple_index(html, &index_url).unwrap();
+        assert_eq!(files.len(), 3);
+        assert_eq!(files[0].sha256.as_deref(), Some("ab"));
+        assert!(
+            files[0]
+                .url
+                .path()
+                .ends_with("my_tool-1.4.0-py3-none-any.whl")
+        );
+        assert_eq!(
+            files[2].url.as_str(),
+            "https://gitlab.com/api/v4/projects/1/packages/pypi/files/ef/my_tool-1.2.0.tar.gz"
+        );
+        assert_eq!(files[0].version("My-Tool").as_deref(), Some("1.4.0"));
+        assert_eq!(files[1].version("my_tool").as_deref(), Some("1.3.0"));
+        assert_eq!(files[1].version("other"), None);
+    }
+
+    #[test]
+    fn test_select_version() {
+        let package = |name: &str, version: &str, created_at: &str| PackageInfo {
+            id: 1,
+            name: name.to_string(),
+            version: version.to_string(),
+            tags: vec![],
+            created_at: Some(created_at.to_string()),
+            last_downloaded_at: None,
+            package_type: Some(PackageType::Pypi),
+            status: None,
+            project_id: None,
+            project_path: None,
+        };
+        let packages = vec![
+            package("my-tool", "1.3.0", "2025-01-01T00:00:00Z"),
+            package("My.Tool", "1.4.0", "2025-02-01T00:00:00Z"),
+            package("my-tool-extra", "2.0.0", "2025-03-01T00:00:00Z"),
+        ];
+        let selected = select_version(packages, "my_tool", &VersionStrategy::CreatedAt).unwrap();
+        assert_eq!(selected.version, "1.4.0");
+    }
+}
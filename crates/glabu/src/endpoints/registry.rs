@@ -0,0 +1,549 @@
+//! Browse the container registry of a GitLab project and clean up its tags.
+//!
+//! `registry-prune` uses the bulk tag deletion endpoint, which runs in the background
+//! and may only be called once per hour per repository. GitLab never deletes `latest`,
+//! and matches the regexes against the whole tag name. The same rules run on a schedule
+//! with the cleanup policy of the project, see `registry-policy`.
+//! See gitlab api doc: https://docs.gitlab.com/api/container_registry/
+
+use super::packages::list_pages;
+use super::setup::{gitlab_api_url, gitlab_api_url_with_query, gitlab_token, httpclient};
+use crate::endpoints::PrintOutput;
+use crate::models::{
+    ContainerExpirationPolicy, ContainerExpirationPolicyAttributes, Project, RegistryRepository,
+    RegistryTag,
+};
+use regex::Regex;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Rules of a bulk tag deletion, named like the parameters of the endpoint.
+#[derive(Debug, Clone, Serialize)]
+pub struct PruneRules {
+    /// Tags matching this regex are deleted
+    pub name_regex_delete: String,
+    /// Tags matching this regex are kept, even if they match `name_regex_delete`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name_regex_keep: Option<String>,
+    /// Number of the newest matching tags to keep
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub keep_n: Option<u32>,
+    /// Only delete tags older than this, e.g. `7d` or `1month`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub older_than: Option<String>,
+}
+
+impl PruneRules {
+    /// Whether the tag details (`created_at`) are needed to apply the rules.
+    pub fn needs_details(&self) -> bool {
+        self.keep_n.is_some() || self.older_than.is_some()
+    }
+
+    /// Names of the tags GitLab would delete, in the same order of filters:
+    /// name rules, then keep_n on the newest, then older_than.
+    pub fn select(&self, tags: &[RegistryTag], now: i64) -> Result<Vec<String>, String> {
+        let full_match = |regex: &str| {
+            Regex::new(&format!("^(?:{})$", regex))
+                .map_err(|e| format!("InvalidRegex: {}: {}", regex, e))
+        };
+        let delete = full_match(&self.name_regex_delete)?;
+        let keep = self
+            .name_regex_keep
+            .as_deref()
+            .map(full_match)
+            .transpose()?;
+        let cutoff = match &self.older_than {
+            Some(older_than) => Some(
+                now - parse_duration(older_than)
+                    .ok_or(format!("InvalidDuration: {}", older_than))?,
+            ),
+            None => None,
+        };
+
+        let mut selected: Vec<&RegistryTag> = tags
+            .iter()
+            .filter(|t| t.name != "latest" && delete.is_match(&t.name))
+            .filter(|t| !keep.as_ref().is_some_and(|keep| keep.is_match(&t.name)))
+            .collect();
+        let created_at = |tag: &RegistryTag| tag.created_at.as_deref().and_then(unix_timestamp);
+        if let Some(keep_n) = self.keep_n {
+            selected.sort_by_key(|t| std::cmp::Reverse(created_at(t)));
+            selected.drain(..selected.len().min(keep_n as usize));
+        }
+        if let Some(cutoff) = cutoff {
+            selected.retain(|t| created_at(t).is_some_and(|created| created < cutoff));
+        }
+        let mut names: Vec<String> = selected.into_iter().map(|t| t.name.clone()).collect();
+        names.sort();
+        Ok(names)
+    }
+}
+
+/// Parses a duration like `90s`, `12h`, `7d`, `2 weeks` or `1month` into seconds.
+pub fn parse_duration(duration: &str) -> Option<i64> {
+    let re = Regex::new(r"^\s*(\d+)\s*([a-z]+)\s*$").unwrap();
+    let captures = re.captures(duration)?;
+    let n: i64 = captures[1].parse().ok()?;
+    let unit = match captures[2].trim_end_matches('s') {
+        "" | "sec" | "second" => 1,
+        "m" | "min" | "minute" => 60,
+        "h" | "hr" | "hour" => 3600,
+        "d" | "day" => 86400,
+        "w" | "week" => 7 * 86400,
+        "mo" | "month" => 30 * 86400,
+        "y" | "year" => 365 * 86400,
+        _ => return None,
+    };
+    Some(n * unit)
+}
+
+/// Seconds since the epoch of an RFC 3339 timestamp such as `2025-01-06T16:49:51.272+01:00`.
+pub fn unix_timestamp(timestamp: &str) -> Option<i64> {
+    let re = Regex::new(
+        r"^(\d{4})-(\d{2})-(\d{2})[T ](\d{2}):(\d{2}):(\d{2})(?:\.\d+)?(Z|([+-])(\d{2}):?(\d{2}))?$",
+    )
+    .unwrap();
+    let c = re.captures(timestamp)?;
+    let field = |i: usize| c[i].parse::<i64>().ok();
+    let (year, month, day) = (field(1)?, field(2)?, field(3)?);
+    // days from civil, see http://howardhinnant.github.io/date_algorithms.html
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let doy = (153 * (month + if month > 2 { -3 } else { 9 }) + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    let days = era * 146097 + doe - 719468;
+    let mut seconds = days * 86400 + field(4)? * 3600 + field(5)? * 60 + field(6)?;
+    if c.get(8).is_some() {
+        let offset = field(9)? * 3600 + field(10)? * 60;
+        seconds -= if &c[8] == "-" { -offset } else { offset };
+    }
+    Some(seconds)
+}
+
+/// Values of the cleanup policy that GitLab accepts.
+pub const POLICY_CADENCES: &[&str] = &["1d", "7d", "14d", "1month", "3month"];
+pub const POLICY_KEEP_N: &[u32] = &[1, 5, 10, 25, 50, 100];
+pub const POLICY_OLDER_THAN: &[&str] = &["7d", "14d", "30d", "90d"];
+
+/// Checks a cleanup policy change before it is sent: the regexes must compile,
+/// the other values must be ones GitLab accepts.
+pub fn validate_policy(attributes: &ContainerExpirationPolicyAttributes) -> Result<(), String> {
+    for (field, regex) in [
+        ("name_regex", &attributes.name_regex),
+        ("name_regex_keep", &attributes.name_regex_keep),
+    ] {
+        if let Some(regex) = regex {
+            Regex::new(regex).map_err(|e| format!("InvalidRegex: {} {}: {}", field, regex, e))?;
+        }
+    }
+    let check = |field: &str, value: Option<String>, allowed: Vec<String>| match value {
+        Some(value) if !allowed.contains(&value) => Err(format!(
+            "InvalidValue: {} {}, expected one of {}",
+            field,
+            value,
+            allowed.join(", ")
+        )),
+        _ => Ok(()),
+    };
+    let strings = |values: &[&str]| values.iter().map(|v| v.to_string()).collect();
+    check(
+        "cadence",
+        attributes.cadence.clone(),
+        strings(POLICY_CADENCES),
+    )?;
+    check(
+        "older_than",
+        attributes.older_than.clone(),
+        strings(POLICY_OLDER_THAN),
+    )?;
+    check(
+        "keep_n",
+        attributes.keep_n.map(|n| n.to_string()),
+        POLICY_KEEP_N.iter().map(|n| n.to_string()).collect(),
+    )
+}
+
+/// Result of `registry-prune`.
+#[derive(Debug, Serialize)]
+pub struct RegistryPruneOutput {
+    pub repository: String,
+    pub dry_run: bool,
+    #[serde(flatten)]
+    pub rules: PruneRules,
+    /// Tags that would be deleted, only listed in a dry run
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tags: Option<Vec<String>>,
+}
+
+#[derive(Debug, Clone)]
+pub struct RegistryOp {
+    ///  Your project ID or URL-encoded path
+    pub project_id: String,
+}
+
+impl RegistryOp {
+    pub fn new(project_id: &str) -> Self {
+        Self {
+            project_id: project_id.to_string(),
+        }
+    }
+
+    fn repositories_path(&self, rest: &str) -> String {
+        format!(
+            "/projects/{}/registry/repositories{}",
+            &self.project_id, rest
+        )
+    }
+
+    pub async fn repositories(
+        &self,
+    ) -> Result<Vec<RegistryRepository>, Box<dyn std::error::Error>> {
+        get_all_pages(&self.repositories_path(""), &[("tags_count", "true")]).await
+    }
+
+    /// Finds a repository by id, path (e.g. `puterize/glabu/builder`) or name.
+    pub async fn repository(
+        &self,
+        repository: &str,
+    ) -> Result<RegistryRepository, Box<dyn std::error::Error>> {
+        self.repositories()
+            .await?
+            .into_iter()
+            .find(|r| {
+                r.id.to_string() == repository || r.path == repository || r.name == repository
+            })
+            .ok_or(format!("RepositoryNotFound: {}", repository).into())
+    }
+
+    pub async fn tags(
+        &self,
+        repository_id: u64,
+    ) -> Result<Vec<RegistryTag>, Box<dyn std::error::Error>> {
+        get_all_pages(
+            &self.repositories_path(&format!("/{}/tags", repository_id)),
+            &[],
+        )
+        .await
+    }
+
+    /// Fetches the details of a tag, which include digest, size and creation time.
+    pub async fn tag(
+        &self,
+        repository_id: u64,
+        tag: &str,
+    ) -> Result<RegistryTag, Box<dyn std::error::Error>> {
+        let url = gitlab_api_url(&self.repositories_path(&format!(
+            "/{}/tags/{}",
+            repository_id,
+            urlencoding::encode(tag)
+        )))?;
+        let response = httpclient()
+            .get(url)
+            .header("Private-Token", gitlab_token())
+            .send()
+            .await?;
+        let status = response.status();
+        if status == 404 {
+            return Err(format!("TagNotFound: {}", tag).into());
+        }
+        if !status.is_success() {
+            return Err(format!("Getting tag failed with status: {}", status).into());
+        }
+        Ok(response.json().await?)
+    }
+
+    /// Lists the tags with their details, one request per tag.
+    pub async fn tags_with_details(
+        &self,
+        repository_id: u64,
+    ) -> Result<Vec<RegistryTag>, Box<dyn std::error::Error>> {
+        let tags = self.tags(repository_id).await?;
+        eprintln!("Fetching details of {} tags", tags.len());
+        let mut details = vec![];
+        for tag in tags {
+            details.push(self.tag(repository_id, &tag.name).await?);
+        }
+        Ok(details)
+    }
+
+    pub async fn delete_tag(
+        &self,
+        repository_id: u64,
+        tag: &str,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let url = gitlab_api_url(&self.repositories_path(&format!(
+            "/{}/tags/{}",
+            repository_id,
+            urlencoding::encode(tag)
+        )))?;
+        let response = httpclient()
+            .delete(url)
+            .header("Private-Token", gitlab_token())
+            .send()
+            .await?;
+        let status = response.status();
+        if status == 404 {
+            return Err(format!("TagNotFound: {}", tag).into());
+        }
+        if !status.is_success() {
+            return Err(format!(
+                "Deleting tag {} failed with status: {}, and message: {}",
+                tag,
+                status,
+                response.text().await?
+            )
+            .into());
+        }
+        Ok(())
+    }
+
+    /// The cleanup policy of the project, `None` if the registry is disabled.
+    pub async fn policy(
+        &self,
+    ) -> Result<Option<ContainerExpirationPolicy>, Box<dyn std::error::Error>> {
+        let url = gitlab_api_url(&format!("/projects/{}", &self.project_id))?;
+        let response = httpclient()
+            .get(url)
+            .header("Private-Token", gitlab_token())
+            .send()
+            .await?;
+        let status = response.status();
+        if status == 404 {
+            return Err(format!("ProjectNotFound: {}", &self.project_id).into());
+        }
+        if !status.is_success() {
+            return Err(format!("Getting project failed with status: {}", status).into());
+        }
+        let project: Project = response.json().await?;
+        Ok(project.container_expiration_policy)
+    }
+
+    /// Updates the cleanup policy after validating the change, returning the new policy.
+    pub async fn set_policy(
+        &self,
+        attributes: &ContainerExpirationPolicyAttributes,
+    ) -> Result<Option<ContainerExpirationPolicy>, Box<dyn std::error::Error>> {
+        validate_policy(attributes)?;
+        let url = gitlab_api_url(&format!("/projects/{}", &self.project_id))?;
+        let response = httpclient()
+            .put(url)
+            .header("Private-Token", gitlab_token())
+            .json(&serde_json::json!({ "container_expiration_policy_attributes": attributes }))
+            .send()
+            .await?;
+        let status = response.status();
+        if !status.is_success() {
+            return Err(format!(
+                "Updating the cleanup policy failed with status: {}, and message: {}",
+                status,
+                response.text().await?
+            )
+            .into());
+        }
+        let project: Project = response.json().await?;
+        Ok(project.container_expiration_policy)
+    }
+
+    /// Deletes tags in bulk, or lists what would be deleted when `dry_run` is set.
+    pub async fn prune(
+        &self,
+        repository: &str,
+        rules: PruneRules,
+        dry_run: bool,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let repository = self.repository(repository).await?;
+        let tags = if !dry_run {
+            // check the rules locally, GitLab only reports errors in the background job
+            rules.select(&[], now())?;
+            None
+        } else if rules.needs_details() {
+            let tags = self.tags_with_details(repository.id).await?;
+            Some(rules.select(&tags, now())?)
+        } else {
+            let tags = self.tags(repository.id).await?;
+            Some(rules.select(&tags, now())?)
+        };
+
+        if !dry_run {
+            let mut query = vec![("name_regex_delete", rules.name_regex_delete.clone())];
+            if let Some(keep) = &rules.name_regex_keep {
+                query.push(("name_regex_keep", keep.clone()));
+            }
+            if let Some(keep_n) = rules.keep_n {
+                query.push(("keep_n", keep_n.to_string()));
+            }
+            if let Some(older_than) = &rules.older_than {
+                query.push(("older_than", older_than.clone()));
+            }
+            let url = gitlab_api_url_with_query(
+                &self.repositories_path(&format!("/{}/tags", repository.id)),
+                &query,
+            )?;
+            let response = httpclient()
+                .delete(url)
+                .header("Private-Token", gitlab_token())
+                .send()
+                .await?;
+            let status = response.status();
+            if !status.is_success() {
+                return Err(format!(
+                    "Bulk tag deletion failed with status: {}, and message: {}",
+                    status,
+                    response.text().await?
+                )
+                .into());
+            }
+            eprintln!("Tag deletion of {} scheduled", &repository.path);
+        }
+        let msg = PrintOutput {
+            status: "ok".to_string(),
+            output: RegistryPruneOutput {
+                repository: repository.path,
+                dry_run,
+                rules,
+                tags,
+            },
+        };
+        println!("{}", serde_json::to_string_pretty(&msg)?);
+        Ok(())
+    }
+}
+
+fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or_default()
+}
+
+/// Gets all pages of a list endpoint.
+async fn get_all_pages<T: DeserializeOwned>(
+    path: &str,
+    query: &[(&str, &str)],
+) -> Result<Vec<T>, Box<dyn std::error::Error>> {
+    let per_page = 100;
+    list_pages(per_page, |page| async move {
+        let mut page_query: Vec<(&str, String)> =
+            query.iter().map(|(k, v)| (*k, v.to_string())).collect();
+        page_query.push(("page", page.to_string()));
+        page_query.push(("per_page", per_page.to_string()));
+        let url = gitlab_api_url_with_query(path, &page_query)?;
+        let response = httpclient()
+            .get(url)
+            .header("Private-Token", gitlab_token())
+            .send()
+            .await?;
+        let status = response.status();
+        if status == 404 {
+            return Err(format!("NotFound: {}", path).into());
+        }
+        if !status.is_success() {
+            return Err(format!("Listing {} failed with status: {}", path, status).into());
+        }
+        Ok(response.json().await?)
+    })
+    .await
+}
+
+#[cfg(test)]
+mod registry_tests {
+    use super::*;
+
+    fn tag(name: &str, created_at: &str) -> RegistryTag {
+        RegistryTag {
+            name: name.to_string(),
+            path: String::new(),
+            location: String::new(),
+            revision: None,
+            short_revision: None,
+            digest: None,
+            created_at: Some(created_at.to_string()),
+            total_size: None,
+        }
+    }
+
+    #[test]
+    fn test_unix_timestamp_and_duration() {
+        assert_eq!(unix_timestamp("1970-01-01T00:00:00Z"), Some(0));
+        assert_eq!(
+            unix_timestamp("2025-01-06T16:49:51.272+01:00"),
+            Some(1736178591)
+        );
+        assert_eq!(parse_duration("7d"), Some(7 * 86400));
+        assert_eq!(parse_duration("2 weeks"), Some(14 * 86400));
+        assert_eq!(parse_duration("1month"), Some(30 * 86400));
+        assert_eq!(parse_duration("soon"), None);
+    }
+
+    #[test]
+    fn test_prune_rules() {
+        let now = unix_timestamp("2025-03-01T00:00:00Z").unwrap();
+        let tags = vec![
+            tag("latest", "2025-01-01T00:00:00Z"),
+            tag("v1.0.0", "2025-01-01T00:00:00Z"),
+            tag("abc123-amd64", "2025-01-01T00:00:00Z"),
+            tag("def456-amd64", "2025-02-01T00:00:00Z"),
+            tag("aaa111-arm64", "2025-02-27T00:00:00Z"),
+            tag("bbb222-arm64", "2025-02-28T00:00:00Z"),
+        ];
+        let rules = PruneRules {
+            name_regex_delete: ".*".to_string(),
+            name_regex_keep: Some(r"v\d+.*".to_string()),
+            keep_n: None,
+            older_than: None,
+        };
+        assert_eq!(
+            rules.select(&tags, now).unwrap(),
+            vec![
+                "aaa111-arm64",
+                "abc123-amd64",
+                "bbb222-arm64",
+                "def456-amd64"
+            ]
+        );
+        let rules = PruneRules {
+            name_regex_delete: ".*-(amd64|arm64)".to_string(),
+            name_regex_keep: None,
+            keep_n: Some(1),
+            older_than: Some("7d".to_string()),
+        };
+        // bbb222 is the newest and kept, aaa111 is younger than 7 days
+        assert_eq!(
+            rules.select(&tags, now).unwrap(),
+            vec!["abc123-amd64", "def456-amd64"]
+        );
+        let invalid = PruneRules {
+            name_regex_delete: "(".to_string(),
+            ..rules
+        };
+        assert!(invalid.select(&[], now).is_err());
+    }
+
+    #[test]
+    fn test_validate_policy() {
+        let mut attributes = ContainerExpirationPolicyAttributes {
+            cadence: Some("7d".to_string()),
+            keep_n: Some(10),
+            older_than: Some("14d".to_string()),
+            name_regex: Some(".*-(amd64|arm64)".to_string()),
+            name_regex_keep: Some(r"v\d+\..*".to_string()),
+            ..Default::default()
+        };
+        assert!(validate_policy(&attributes).is_ok());
+        attributes.name_regex_keep = Some("(?!latest)".to_string());
+        assert!(
+            validate_policy(&attributes)
+                .unwrap_err()
+                .starts_with("InvalidRegex")
+        );
+        attributes.name_regex_keep = None;
+        attributes.keep_n = Some(7);
+        assert!(
+            validate_policy(&attributes)
+                .unwrap_err()
+                .starts_with("InvalidValue: keep_n")
+        );
+    }
+}
@@ -1,47 +1,355 @@
+use super::packages::{
+    PackageType, ProjectPackageListOp, generic_package_file_url, latest_uploads, list_pages,
+};
 use super::projects::project_get_by_id;
-use super::setup::{gitlab_api_url, gitlab_token, httpclient};
-use crate::models::ProjectRelease;
-use either::Either;
+use super::setup::{gitlab_api_url, gitlab_api_url_with_query, gitlab_token, httpclient};
+use crate::models::{ProjectRelease, ReleaseCreatePayload, ReleaseLinkPayload, ReleaseLinkType};
+use reqwest::Url;
+use serde::de::DeserializeOwned;
+use std::collections::BTreeSet;
 
+/// Releases of a project, newest first.
+/// See https://docs.gitlab.com/api/releases/
 pub struct ProjectReleasesGet {
-    pub project_id: u64,
+    /// ID or URL-encoded path of the project.
+    pub project_id: String,
 }
 
 impl ProjectReleasesGet {
     pub fn new(project_id: u64) -> Self {
-        Self { project_id }
+        Self::for_project(&project_id.to_string())
+    }
+    /// For a project ID or URL-encoded path, without looking the project up.
+    pub fn for_project(project_id: &str) -> Self {
+        Self {
+            project_id: project_id.to_string(),
+        }
     }
     pub async fn from_full_path(full_path: &str) -> Result<Self, Box<dyn std::error::Error>> {
         let project_id = project_get_by_id(full_path).await?.id;
         Ok(Self::new(project_id))
     }
-    pub async fn run(
+
+    /// Lists the releases from all pages, ordered by `released_at`.
+    pub async fn run(&self) -> Result<Vec<ProjectRelease>, Box<dyn std::error::Error>> {
+        let per_page = 100;
+        let not_found = format!(
+            "ProjectNotFound: {}",
+            urlencoding::decode(&self.project_id)?
+        );
+        list_pages(per_page, |page| {
+            let not_found = &not_found;
+            async move {
+                let url = gitlab_api_url_with_query(
+                    &format!("/projects/{}/releases", self.project_id),
+                    &[
+                        ("page", page.to_string()),
+                        ("per_page", per_page.to_string()),
+                    ],
+                )?;
+                self.get(url, not_found).await
+            }
+        })
+        .await
+    }
+
+    /// The release of a tag.
+    pub async fn get_by_tag(
+        &self,
+        tag: &str,
+    ) -> Result<ProjectRelease, Box<dyn std::error::Error>> {
+        let url = gitlab_api_url(&format!(
+            "/projects/{}/releases/{}",
+            self.project_id,
+            urlencoding::encode(tag)
+        ))?;
+        self.get(url, &format!("ReleaseNotFound: no release for tag {}", tag))
+            .await
+    }
+
+    /// The most recent release by `released_at`.
+    pub async fn latest(&self) -> Result<ProjectRelease, Box<dyn std::error::Error>> {
+        let url = gitlab_api_url(&format!(
+            "/projects/{}/releases/permalink/latest",
+            self.project_id
+        ))?;
+        self.get(url, "ReleaseNotFound: the project has no releases")
+            .await
+    }
+
+    /// Gets and parses `url`, failing with `not_found` on a 404.
+    async fn get<T: DeserializeOwned>(
         &self,
-    ) -> Result<Either<String, Vec<ProjectRelease>>, Box<dyn std::error::Error>> {
+        url: Url,
+        not_found: &str,
+    ) -> Result<T, Box<dyn std::error::Error>> {
         let response = httpclient()
-            .get(gitlab_api_url(&format!(
-                "/projects/{}/releases",
-                self.project_id
-            ))?)
+            .get(url)
             .header("Private-Token", gitlab_token())
             .send()
             .await?;
-        let json_str = response.text().await?;
-        let res = serde_json::from_str(&json_str)?;
-        Ok(res)
+        let status = response.status();
+        if status == 404 {
+            return Err(not_found.into());
+        }
+        if !status.is_success() {
+            return Err(format!(
+                "Getting releases failed with status: {}, and message: {}",
+                status,
+                response.text().await?
+            )
+            .into());
+        }
+        Ok(response.json().await?)
     }
+}
 
-    pub async fn latest(&self) -> Result<ProjectRelease, Box<dyn std::error::Error>> {
+/// Creates a release for a tag, see https://docs.gitlab.com/api/releases/#create-a-release
+#[derive(Debug, Clone)]
+pub struct ReleaseCreate {
+    /// ID or URL-encoded path of the project.
+    pub project_id: String,
+    pub payload: ReleaseCreatePayload,
+}
+
+impl ReleaseCreate {
+    pub fn new(project_id: &str, tag_name: &str) -> Self {
+        Self {
+            project_id: project_id.to_string(),
+            payload: ReleaseCreatePayload {
+                tag_name: tag_name.to_string(),
+                ..Default::default()
+            },
+        }
+    }
+    pub fn name(mut self, name: Option<String>) -> Self {
+        self.payload.name = name;
+        self
+    }
+    pub fn description(mut self, description: Option<String>) -> Self {
+        self.payload.description = description;
+        self
+    }
+    /// Commit, branch or tag the tag is created from when it does not exist.
+    pub fn git_ref(mut self, git_ref: Option<String>) -> Self {
+        self.payload.git_ref = git_ref;
+        self
+    }
+    pub fn milestones(mut self, milestones: Vec<String>) -> Self {
+        self.payload.milestones = milestones;
+        self
+    }
+    /// ISO 8601 date, defaults to now on the server.
+    pub fn released_at(mut self, released_at: Option<String>) -> Self {
+        self.payload.released_at = released_at;
+        self
+    }
+    pub fn link(mut self, link: ReleaseLinkPayload) -> Self {
+        self.payload.assets.links.push(link);
+        self
+    }
+
+    /// Adds a package link for every file of a generic package version.
+    /// Re-uploaded files only link to their latest upload.
+    pub async fn assets_from_package(
+        mut self,
+        package_name: &str,
+        package_version: &str,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let op = ProjectPackageListOp::new(&self.project_id)
+            .package_type(Some(PackageType::Generic))
+            .package_name(Some(package_name.to_string()))
+            .package_version(Some(package_version.to_string()));
+        let package = op
+            .list()
+            .await?
+            .into_iter()
+            .find(|p| p.name == package_name && p.version == package_version)
+            .ok_or_else(|| {
+                format!(
+                    "PackageNotFound: no generic package {}@{}",
+                    package_name, package_version
+                )
+            })?;
+        for file in latest_uploads(op.package_files(&package).await?) {
+            let url = generic_package_file_url(
+                &self.project_id,
+                package_name,
+                package_version,
+                &file.file_name,
+            )?;
+            self = self.link(ReleaseLinkPayload {
+                direct_asset_path: Some(format!("/{}", file.file_name)),
+                name: file.file_name,
+                url: url.to_string(),
+                link_type: Some(ReleaseLinkType::Package),
+            });
+        }
+        Ok(self)
+    }
+
+    /// Whether the tag of the release exists in the repository.
+    async fn tag_exists(&self) -> Result<bool, Box<dyn std::error::Error>> {
+        let url = gitlab_api_url(&format!(
+            "/projects/{}/repository/tags/{}",
+            self.project_id,
+            urlencoding::encode(&self.payload.tag_name)
+        ))?;
+        let response = httpclient()
+            .get(url)
+            .header("Private-Token", gitlab_token())
+            .send()
+            .await?;
+        match response.status().as_u16() {
+            404 => Ok(false),
+            s if (200..300).contains(&s) => Ok(true),
+            s => Err(format!(
+                "Getting tag {} failed with status: {}, and message: {}",
+                self.payload.tag_name,
+                s,
+                response.text().await?
+            )
+            .into()),
+        }
+    }
+
+    /// Link names used more than once, GitLab rejects the release for any of them.
+    pub fn duplicate_link_names(&self) -> Vec<&str> {
+        let mut seen = BTreeSet::new();
+        let duplicates: BTreeSet<&str> = self
+            .payload
+            .assets
+            .links
+            .iter()
+            .map(|link| link.name.as_str())
+            .filter(|name| !seen.insert(*name))
+            .collect();
+        duplicates.into_iter().collect()
+    }
+
+    pub async fn run(&self) -> Result<ProjectRelease, Box<dyn std::error::Error>> {
+        let duplicates = self.duplicate_link_names();
+        if !duplicates.is_empty() {
+            return Err(format!(
+                "DuplicateAssetName: {} linked more than once, the packages share file names",
+                duplicates.join(", ")
+            )
+            .into());
+        }
+        if self.payload.git_ref.is_none() && !self.tag_exists().await? {
+            return Err(format!(
+                "TagNotFound: {} does not exist, pass a ref to create it from",
+                self.payload.tag_name
+            )
+            .into());
+        }
         let response = httpclient()
-            .get(gitlab_api_url(&format!(
-                "/projects/{}/releases/permalink/latest",
+            .post(gitlab_api_url(&format!(
+                "/projects/{}/releases",
                 self.project_id
             ))?)
             .header("Private-Token", gitlab_token())
+            .json(&self.payload)
             .send()
             .await?;
-        let json_str = response.text().await?;
-        let res: ProjectRelease = serde_json::from_str(&json_str)?;
-        Ok(res)
+        let status = response.status();
+        if status == 409 {
+            return Err(format!(
+                "ReleaseExists: {} already has a release",
+                self.payload.tag_name
+            )
+            .into());
+        }
+        if status != 201 {
+            return Err(format!(
+                "Creating release failed with status: {}, and message: {}",
+                status,
+                response.text().await?
+            )
+            .into());
+        }
+        Ok(response.json().await?)
+    }
+}
+
+#[cfg(test)]
+mod releases_tests {
+    use super::*;
+    use crate::models::{ReleaseLink, ReleaseLinkType};
+
+    #[test]
+    fn test_parse_release() {
+        let json = r#"{
+            "name": "v0.2.0",
+            "tag_name": "v0.2.0",
+            "description": null,
+            "created_at": "2025-03-01T10:00:00.000Z",
+            "released_at": "2025-03-01T10:00:00.000Z",
+            "upcoming_release": false,
+            "author": {"id": 1, "username": "kc", "name": "KC", "state": "active",
+                       "locked": false, "avatar_url": null, "web_url": "https://gitlab.com/kc"},
+            "commit_path": "/puterize/glabu/-/commit/abc",
+            "tag_path": "/puterize/glabu/-/tags/v0.2.0",
+            "assets": {
+                "count": 1,
+                "sources": [],
+                "links": [{"id": 7, "name": "glabu", "url": "https://x/glabu",
+                           "direct_asset_url": "https://x/glabu", "link_type": "package"}]
+            },
+            "evidences": [],
+            "_links": {"self": "https://gitlab.com/puterize/glabu/-/releases/v0.2.0"}
+        }"#;
+        let release: ProjectRelease = serde_json::from_str(json).unwrap();
+        assert_eq!(release.tag_name, "v0.2.0");
+        assert!(release.description.is_none() && release.commit.is_none());
+        assert_eq!(
+            release.assets.links[0].link_type,
+            Some(ReleaseLinkType::Package)
+        );
+        assert!(release.milestones.is_empty());
+
+        let link: ReleaseLink = serde_json::from_str(
+            r#"{"id": 8, "name": "docs", "url": "https://x/docs", "link_type": "manual"}"#,
+        )
+        .unwrap();
+        assert_eq!(link.link_type, Some(ReleaseLinkType::Unknown));
+    }
+
+    #[test]
+    fn test_create_payload() {
+        let create = ReleaseCreate::new("puterize%2Fglabu", "v0.3.0")
+            .git_ref(Some("main".to_string()))
+            .milestones(vec!["0.3".to_string()])
+            .link(ReleaseLinkPayload {
+                name: "glabu.tar.gz".to_string(),
+                url: "https://x/glabu.tar.gz".to_string(),
+                direct_asset_path: Some("/glabu.tar.gz".to_string()),
+                link_type: Some(ReleaseLinkType::Package),
+            });
+        let json = serde_json::to_value(&create.payload).unwrap();
+        assert_eq!(json["ref"], "main");
+        assert_eq!(json["milestones"][0], "0.3");
+        assert_eq!(json["assets"]["links"][0]["link_type"], "package");
+        assert!(json.get("name").is_none() && json.get("released_at").is_none());
+    }
+
+    #[test]
+    fn test_duplicate_link_names() {
+        let link = |name: &str, url: &str| ReleaseLinkPayload {
+            name: name.to_string(),
+            url: url.to_string(),
+            direct_asset_path: Some(format!("/{}", name)),
+            link_type: Some(ReleaseLinkType::Package),
+        };
+        let create = ReleaseCreate::new("1", "v0.3.0")
+            .link(link("glabu", "https://x/cli/glabu"))
+            .link(link("SHA256SUMS", "https://x/cli/SHA256SUMS"))
+            .link(link("glabu.1", "https://x/docs/glabu.1"));
+        assert!(create.duplicate_link_names().is_empty());
+        let create = create
+            .link(link("SHA256SUMS", "https://x/docs/SHA256SUMS"))
+            .link(link("glabu", "https://x/docs/glabu"))
+            .link(link("glabu", "https://x/extra/glabu"));
+        assert_eq!(create.duplicate_link_names(), vec!["SHA256SUMS", "glabu"]);
     }
 }
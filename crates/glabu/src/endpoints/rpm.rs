@@ -0,0 +1,263 @@
+//! Upload to the RPM registry of a GitLab project.
+//!
+//! Name, version and architecture are read from the header of the `.rpm`,
+//! GitLab lists the package with the version `<version>-<release>`.
+//! See gitlab api doc: https://docs.gitlab.com/user/packages/rpm_repository/
+
+use super::setup::{gitlab_api_url, httpclient, with_basic_auth};
+use crate::endpoints::PrintOutput;
+use reqwest::multipart::{Form, Part};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::path::Path;
+
+const LEAD_SIZE: usize = 96;
+const HEADER_MAGIC: [u8; 4] = [0x8e, 0xad, 0xe8, 0x01];
+
+const TAG_NAME: u32 = 1000;
+const TAG_VERSION: u32 = 1001;
+const TAG_RELEASE: u32 = 1002;
+const TAG_EPOCH: u32 = 1003;
+const TAG_SUMMARY: u32 = 1004;
+const TAG_ARCH: u32 = 1022;
+
+const TYPE_INT32: u32 = 4;
+const TYPE_STRING: u32 = 6;
+const TYPE_I18NSTRING: u32 = 9;
+
+/// Metadata from the main header of an `.rpm`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct RpmMetadata {
+    pub name: String,
+    pub version: String,
+    pub release: String,
+    pub epoch: Option<u32>,
+    pub arch: String,
+    pub summary: Option<String>,
+}
+
+impl RpmMetadata {
+    /// Parses the lead, skips the signature header and reads the main header.
+    pub fn parse(content: &[u8]) -> Result<Self, String> {
+        if !content.starts_with(&[0xed, 0xab, 0xee, 0xdb]) {
+            return Err("InvalidRpm: not an rpm file".to_string());
+        }
+        let (_, signature_size) = read_header(content, LEAD_SIZE)?;
+        // the signature header is padded to 8 bytes
+        let offset = LEAD_SIZE + signature_size.div_ceil(8) * 8;
+        let (header, _) = read_header(content, offset)?;
+
+        let mut metadata = RpmMetadata::default();
+        for entry in &header.entries {
+            match (entry.tag, entry.kind) {
+                (TAG_NAME, TYPE_STRING) => metadata.name = header.string(entry)?,
+                (TAG_VERSION, TYPE_STRING) => metadata.version = header.string(entry)?,
+                (TAG_RELEASE, TYPE_STRING) => metadata.release = header.string(entry)?,
+                (TAG_ARCH, TYPE_STRING) => metadata.arch = header.string(entry)?,
+                (TAG_SUMMARY, TYPE_STRING | TYPE_I18NSTRING) => {
+                    metadata.summary = Some(header.string(entry)?)
+                }
+                (TAG_EPOCH, TYPE_INT32) => metadata.epoch = Some(header.int32(entry)?),
+                _ => {}
+            }
+        }
+        if metadata.name.is_empty() || metadata.version.is_empty() {
+            return Err("InvalidRpm: name and version are required".to_string());
+        }
+        Ok(metadata)
+    }
+
+    /// The version as GitLab lists it, `<version>-<release>`.
+    pub fn full_version(&self) -> String {
+        match self.release.as_str() {
+            "" => self.version.clone(),
+            release => format!("{}-{}", self.version, release),
+        }
+    }
+}
+
+struct IndexEntry {
+    tag: u32,
+    kind: u32,
+    offset: usize,
+}
+
+struct Header<'a> {
+    entries: Vec<IndexEntry>,
+    store: &'a [u8],
+}
+
+impl Header<'_> {
+    /// First NUL-terminated string at the entry's offset.
+    fn string(&self, entry: &IndexEntry) -> Result<String, String> {
+        let data = self
+            .store
+            .get(entry.offset..)
+            .ok_or("InvalidRpm: entry outside of the header")?;
+        let end = data.iter().position(|b| *b == 0).unwrap_or(data.len());
+        Ok(String::from_utf8_lossy(&data[..end]).to_string())
+    }
+
+    fn int32(&self, entry: &IndexEntry) -> Result<u32, String> {
+        be_u32(self.store, entry.offset)
+    }
+}
+
+fn be_u32(data: &[u8], offset: usize) -> Result<u32, String> {
+    data.get(offset..offset + 4)
+        .map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+        .ok_or("InvalidRpm: truncated header".to_string())
+}
+
+/// Reads a header structure at `offset`, returning it and its size in bytes.
+fn read_header(content: &[u8], offset: usize) -> Result<(Header<'_>, usize), String> {
+    if content.get(offset..offset + 4) != Some(&HEADER_MAGIC[..]) {
+        return Err("InvalidRpm: bad header magic".to_string());
+    }
+    let index_count = be_u32(content, offset + 8)? as usize;
+    let store_size = be_u32(content, offset + 12)? as usize;
+    let index_start = offset + 16;
+    let store_start = index_start + index_count * 16;
+    let store = content
+        .get(store_start..store_start + store_size)
+        .ok_or("InvalidRpm: truncated header")?;
+    let entries = (0..index_count)
+        .map(|i| {
+            let at = index_start + i * 16;
+            Ok(IndexEntry {
+                tag: be_u32(content, at)?,
+                kind: be_u32(content, at + 4)?,
+                offset: be_u32(content, at + 8)? as usize,
+            })
+        })
+        .collect::<Result<_, String>>()?;
+    Ok((
+        Header { entries, store },
+        16 + index_count * 16 + store_size,
+    ))
+}
+
+/// Result of uploading a package.
+#[derive(Debug, Serialize)]
+pub struct RpmUploadOutput {
+    pub file_name: String,
+    pub package_name: String,
+    pub package_version: String,
+    pub arch: String,
+    pub sha256: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct RpmOp {
+    ///  Your project ID or URL-encoded path
+    pub project_id: String,
+}
+
+impl RpmOp {
+    pub fn new(project_id: &str) -> Self {
+        Self {
+            project_id: project_id.to_string(),
+        }
+    }
+
+    pub async fn upload(&self, path: &Path) -> Result<RpmUploadOutput, Box<dyn std::error::Error>> {
+        let content = tokio::fs::read(path).await?;
+        let metadata = RpmMetadata::parse(&content)?;
+        let file_name = path
+            .file_name()
+            .map(|s| s.to_string_lossy().to_string())
+            .ok_or("File name not found")?;
+        let sha256 = hex::encode(Sha256::digest(&content));
+
+        let url = gitlab_api_url(&format!("/projects/{}/packages/rpm", &self.project_id))?;
+        eprintln!(
+            "Uploading {} ({} {})",
+            &file_name,
+            &metadata.name,
+            metadata.full_version()
+        );
+        let form = Form::new().part("file", Part::bytes(content).file_name(file_name.clone()));
+        let response = with_basic_auth(httpclient().post(url))
+            .multipart(form)
+            .send()
+            .await?;
+        let status = response.status();
+        if !status.is_success() {
+            return Err(format!(
+                "Upload failed with status: {}, and message: {}",
+                status,
+                response.text().await?
+            )
+            .into());
+        }
+        Ok(RpmUploadOutput {
+            file_name,
+            package_version: metadata.full_version(),
+            package_name: metadata.name,
+            arch: metadata.arch,
+            sha256,
+        })
+    }
+
+    pub async fn upload_all(
+        &self,
+        paths: &[impl AsRef<Path>],
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let mut outputs = vec![];
+        for path in paths {
+            outputs.push(self.upload(path.as_ref()).await?);
+        }
+        let msg = PrintOutput {
+            status: "ok".to_string(),
+            output: outputs,
+        };
+        println!("{}", serde_json::to_string_pretty(&msg)?);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod rpm_tests {
+    use super::*;
+
+    /// Builds a header structure from (tag, type, data) entries.
+    fn header(entries: &[(u32, u32, &[u8])]) -> Vec<u8> {
+        let mut index = vec![];
+        let mut store: Vec<u8> = vec![];
+        for (tag, kind, data) in entries {
+            for value in [*tag, *kind, store.len() as u32, 1] {
+                index.extend(value.to_be_bytes());
+            }
+            store.extend(*data);
+        }
+        let mut header = HEADER_MAGIC.to_vec();
+        header.extend([0; 4]);
+        header.extend((entries.len() as u32).to_be_bytes());
+        header.extend((store.len() as u32).to_be_bytes());
+        header.extend(index);
+        header.extend(store);
+        header
+    }
+
+    #[test]
+    fn test_parse_rpm_metadata() {
+        let mut rpm = vec![0xed, 0xab, 0xee, 0xdb];
+        rpm.resize(LEAD_SIZE, 0);
+        let signature = header(&[(1000, 4, &[0, 0, 0, 5])]);
+        rpm.resize(LEAD_SIZE + signature.len().div_ceil(8) * 8, 0);
+        rpm[LEAD_SIZE..LEAD_SIZE + signature.len()].copy_from_slice(&signature);
+        rpm.extend(header(&[
+            (TAG_NAME, TYPE_STRING, b"glabu\0"),
+            (TAG_VERSION, TYPE_STRING, b"0.1.0\0"),
+            (TAG_RELEASE, TYPE_STRING, b"1\0"),
+            (TAG_EPOCH, TYPE_INT32, &[0, 0, 0, 2]),
+            (TAG_ARCH, TYPE_STRING, b"x86_64\0"),
+        ]));
+        let metadata = RpmMetadata::parse(&rpm).unwrap();
+        assert_eq!(metadata.name, "glabu");
+        assert_eq!(metadata.full_version(), "0.1.0-1");
+        assert_eq!(metadata.epoch, Some(2));
+        assert_eq!(metadata.arch, "x86_64");
+        assert!(RpmMetadata::parse(b"not an rpm").is_err());
+    }
+}
@@ -1,7 +1,7 @@
 use std::borrow::Borrow;
 use std::sync::OnceLock;
 
-use reqwest::{Client as ReqwestClient, Url};
+use reqwest::{Client as ReqwestClient, RequestBuilder, Url};
 
 pub(crate) const EMPTY_QUERY: &[(&str, &str)] = &[];
 
@@ -18,6 +18,13 @@ pub fn gitlab_host() -> &'static String {
         .get_or_init(|| std::env::var("GITLAB_HOST").unwrap_or("https://gitlab.com".to_string()))
 }
 
+/// Points `GITLAB_HOST` and `GITLAB_TOKEN` at a stand-in, before anything reads them.
+#[cfg(test)]
+pub(crate) fn set_test_instance(host: &str, token: &str) {
+    assert_eq!(GITLAB_HOST.get_or_init(|| host.to_string()), host);
+    GITLAB_TOKEN.get_or_init(|| token.to_string());
+}
+
 pub fn gitlab_api_url_with_query<I, K, V>(
     path: &str,
     query_params: I,
@@ -37,6 +44,37 @@ pub fn gitlab_api_url(path: &str) -> Result<Url, Box<dyn std::error::Error>> {
     Ok(Url::parse(&base_url)?)
 }
 
+/// A GitLab instance other than the one configured by `GITLAB_HOST` and `GITLAB_TOKEN`,
+/// e.g. the target of a package copy across instances.
+#[derive(Debug, Clone)]
+pub struct GitlabInstance {
+    pub host: String,
+    pub token: String,
+}
+
+impl GitlabInstance {
+    /// Reads the token for `host` from the environment variable `token_env`.
+    pub fn from_env(host: &str, token_env: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let token = std::env::var(token_env)
+            .map_err(|_| format!("Environment variable {} is not set", token_env))?;
+        Ok(Self {
+            host: host.trim_end_matches('/').to_string(),
+            token,
+        })
+    }
+
+    pub fn api_url(&self, path: &str) -> Result<Url, Box<dyn std::error::Error>> {
+        let base_url = format!("{}/api/v4{}", self.host, path);
+        Ok(Url::parse(&base_url)?)
+    }
+}
+
+/// Sends the token with basic auth, as the PyPI, Helm, Debian and RPM registries require.
+/// GitLab only looks at the password, the username is arbitrary.
+pub fn with_basic_auth(request: RequestBuilder) -> RequestBuilder {
+    request.basic_auth("glabu", Some(gitlab_token()))
+}
+
 static HTTPCLIENT: OnceLock<ReqwestClient> = OnceLock::new();
 pub fn httpclient() -> &'static ReqwestClient {
     HTTPCLIENT.get_or_init(|| ReqwestClient::new())
@@ -0,0 +1,315 @@
+//! Declarative package sync driven by a `glabu.toml`/`glabu.yaml` manifest.
+//!
+//! Each manifest entry is resolved to an exact version and the sha256 of every
+//! matching file, which is recorded in a lockfile (`glabu.lock` next to the manifest).
+//! Later runs reuse the locked versions, verify local files against the locked checksums
+//! and only download files that are missing or changed.
+
+use super::packages::{
+    GenericPackageOp, ProjectPackageListOp, VersionStrategy, download_file, file_sha256,
+    generic_package_file_url, latest_uploads,
+};
+use crate::endpoints::PrintOutput;
+use crate::models::{LockedFile, LockedPackage, Lockfile, Manifest, ManifestPackage};
+use regex::Regex;
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+
+const MANIFEST_NAMES: &[&str] = &["glabu.toml", "glabu.yaml", "glabu.yml"];
+const LOCKFILE_NAME: &str = "glabu.lock";
+
+/// Result of a package sync.
+#[derive(Debug, Serialize)]
+pub struct PackageSyncOutput {
+    pub lockfile: String,
+    /// Files that were (re-)downloaded
+    pub downloaded: Vec<String>,
+    /// Files that already matched the lockfile
+    pub unchanged: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct PackageSyncOp {
+    pub manifest_path: PathBuf,
+    pub lockfile_path: PathBuf,
+    /// Overrides the `target_dir` of the manifest
+    pub target_dir: Option<PathBuf>,
+    /// Fail instead of resolving when the lockfile is missing or out of date
+    pub locked: bool,
+    /// Resolve all packages again, ignoring the lockfile
+    pub update: bool,
+}
+
+impl PackageSyncOp {
+    /// Creates a sync operation for the given manifest,
+    /// or the first of `glabu.toml`, `glabu.yaml`, `glabu.yml` in the current directory.
+    pub fn new(manifest_path: Option<PathBuf>) -> Result<Self, Box<dyn std::error::Error>> {
+        let manifest_path = match manifest_path {
+            Some(path) => path,
+            None => MANIFEST_NAMES
+                .iter()
+                .map(PathBuf::from)
+                .find(|path| path.is_file())
+                .ok_or("ManifestNotFound: no glabu.toml or glabu.yaml in current directory")?,
+        };
+        let lockfile_path = manifest_dir(&manifest_path).join(LOCKFILE_NAME);
+        Ok(Self {
+            manifest_path,
+            lockfile_path,
+            target_dir: None,
+            locked: false,
+            update: false,
+        })
+    }
+
+    pub fn lockfile_path(mut self, lockfile_path: Option<PathBuf>) -> Self {
+        if let Some(lockfile_path) = lockfile_path {
+            self.lockfile_path = lockfile_path;
+        }
+        self
+    }
+    pub fn target_dir(mut self, target_dir: Option<PathBuf>) -> Self {
+        self.target_dir = target_dir;
+        self
+    }
+    pub fn locked(mut self, locked: bool) -> Self {
+        self.locked = locked;
+        self
+    }
+    pub fn update(mut self, update: bool) -> Self {
+        self.update = update;
+        self
+    }
+
+    pub async fn run(self) -> Result<(), Box<dyn std::error::Error>> {
+        let manifest = read_manifest(&self.manifest_path)?;
+        let lockfile = if self.lockfile_path.is_file() && !self.update {
+            toml::from_str::<Lockfile>(&std::fs::read_to_string(&self.lockfile_path)?)?
+        } else {
+            Lockfile::default()
+        };
+
+        let mut locked_packages = vec![];
+        for requirement in &manifest.packages {
+            let locked = lockfile
+                .packages
+                .iter()
+                .find(|locked| locked.requirement == *requirement);
+            let locked = match locked {
+                Some(locked) => locked.clone(),
+                None if self.locked => {
+                    return Err(format!(
+                        "LockfileOutdated: {} of {} is not locked in {}",
+                        &requirement.name,
+                        &requirement.project,
+                        self.lockfile_path.display()
+                    )
+                    .into());
+                }
+                None => resolve_requirement(requirement).await?,
+            };
+            locked_packages.push(locked);
+        }
+
+        let target_dir = match (&self.target_dir, &manifest.target_dir) {
+            (Some(target_dir), _) => target_dir.clone(),
+            (None, Some(target_dir)) => manifest_dir(&self.manifest_path).join(target_dir),
+            (None, None) => manifest_dir(&self.manifest_path),
+        };
+        std::fs::create_dir_all(&target_dir)?;
+
+        let mut downloaded = vec![];
+        let mut unchanged = vec![];
+        for package in &locked_packages {
+            let project_id = urlencoding::encode(&package.requirement.project).to_string();
+            for file in &package.files {
+                let output_file = target_dir.join(&file.file_name);
+                let output_str = output_file.to_string_lossy().to_string();
+                if output_file.is_file() && file_sha256(&output_file)? == file.sha256 {
+                    unchanged.push(output_str);
+                    continue;
+                }
+                let url = generic_package_file_url(
+                    &project_id,
+                    &package.requirement.name,
+                    &package.resolved_version,
+                    &file.file_name,
+                )?;
+                eprintln!("Downloading {}", &url);
+                download_file(url, &output_file).await?;
+                let sha256 = file_sha256(&output_file)?;
+                if sha256 != file.sha256 {
+                    std::fs::remove_file(&output_file)?;
+                    return Err(format!(
+                        "ChecksumMismatch: {} has sha256 {}, expected {}",
+                        &file.file_name, sha256, &file.sha256
+                    )
+                    .into());
+                }
+                downloaded.push(output_str);
+            }
+        }
+
+        if !self.locked {
+            let lockfile = Lockfile {
+                packages: locked_packages,
+            };
+            std::fs::write(&self.lockfile_path, toml::to_string_pretty(&lockfile)?)?;
+        }
+        let msg = PrintOutput {
+            status: "ok".to_string(),
+            output: PackageSyncOutput {
+                lockfile: self.lockfile_path.to_string_lossy().to_string(),
+                downloaded,
+                unchanged,
+            },
+        };
+        println!("{}", serde_json::to_string_pretty(&msg)?);
+        Ok(())
+    }
+}
+
+fn manifest_dir(manifest_path: &Path) -> PathBuf {
+    manifest_path
+        .parent()
+        .map(Path::to_path_buf)
+        .unwrap_or_default()
+}
+
+/// Reads a manifest, as yaml if the extension says so and as toml otherwise.
+pub fn read_manifest(path: &Path) -> Result<Manifest, Box<dyn std::error::Error>> {
+    let content = std::fs::read_to_string(path)?;
+    let manifest = match path.extension().and_then(|ext| ext.to_str()) {
+        Some("yaml") | Some("yml") => serde_yaml::from_str(&content)?,
+        _ => toml::from_str(&content)?,
+    };
+    Ok(manifest)
+}
+
+/// Resolves a manifest entry to an exact version and the checksums of its matching files.
+async fn resolve_requirement(
+    requirement: &ManifestPackage,
+) -> Result<LockedPackage, Box<dyn std::error::Error>> {
+    let project_id = urlencoding::encode(&requirement.project).to_string();
+    let strategy = requirement
+        .strategy
+        .as_deref()
+        .unwrap_or("created-at")
+        .parse::<VersionStrategy>()?;
+    let package = GenericPackageOp::new(&project_id, &requirement.name, "")
+        .package_version(requirement.version.clone())
+        .version_strategy(strategy)
+        .resolve_package()
+        .await?;
+    let pattern = requirement.files.as_deref().map(Regex::new).transpose()?;
+    let mut files = vec![];
+    for file in latest_uploads(
+        ProjectPackageListOp::new(&project_id)
+            .package_files(&package)
+            .await?,
+    ) {
+        if pattern
+            .as_ref()
+            .is_some_and(|p| !p.is_match(&file.file_name))
+        {
+            continue;
+        }
+        let sha256 = file
+            .file_sha256
+            .ok_or(format!("No sha256 reported for {}", &file.file_name))?;
+        files.push(LockedFile {
+            file_name: file.file_name,
+            sha256,
+        });
+    }
+    if files.is_empty() {
+        return Err(format!(
+            "NoFilesMatched: {}@{} has no matching files",
+            &package.name, &package.version
+        )
+        .into());
+    }
+    eprintln!("Locked {} to version {}", &package.name, &package.version);
+    Ok(LockedPackage {
+        requirement: requirement.clone(),
+        resolved_version: package.version,
+        files,
+    })
+}
+
+#[cfg(test)]
+mod sync_tests {
+    use super::super::test_gitlab;
+    use super::*;
+
+    #[test]
+    fn test_lockfile_roundtrip() -> Result<(), Box<dyn std::error::Error>> {
+        let manifest: Manifest = toml::from_str(
+            r#"
+            target_dir = "tools"
+
+            [[packages]]
+            project = "puterize/prebuilt"
+            name = "glabu"
+            strategy = "^1.4"
+            files = "glabu-x86_64"
+            "#,
+        )?;
+        let lockfile = Lockfile {
+            packages: vec![LockedPackage {
+                requirement: manifest.packages[0].clone(),
+                resolved_version: "1.4.3".to_string(),
+                files: vec![LockedFile {
+                    file_name: "glabu-x86_64".to_string(),
+                    sha256: "abc".to_string(),
+                }],
+            }],
+        };
+        let lockfile: Lockfile = toml::from_str(&toml::to_string_pretty(&lockfile)?)?;
+        assert_eq!(lockfile.packages[0].requirement, manifest.packages[0]);
+        assert_eq!(lockfile.packages[0].resolved_version, "1.4.3");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_resolve_requirement_exact_name() -> Result<(), Box<dyn std::error::Error>> {
+        // GitLab matches names fuzzily, `glabu` also finds the newer `glabu-cli`
+        test_gitlab::respond(
+            "GET",
+            "/projects/sync%2Fprebuilt/packages",
+            200,
+            r#"[
+                {"id": 1, "name": "glabu", "version": "1.4.0", "tags": [],
+                 "created_at": "2025-03-01T10:00:00.000Z", "package_type": "generic"},
+                {"id": 2, "name": "glabu-cli", "version": "1.4.0", "tags": [],
+                 "created_at": "2025-03-02T10:00:00.000Z", "package_type": "generic"}
+            ]"#,
+        );
+        for id in [1, 2] {
+            test_gitlab::respond(
+                "GET",
+                &format!("/projects/sync%2Fprebuilt/packages/{}/package_files", id),
+                200,
+                format!(
+                    r#"[{{"id": {id}, "package_id": {id}, "created_at": "2025-03-01T10:00:00.000Z",
+                         "file_name": "glabu-x86_64", "file_sha256": "sha-of-{id}"}}]"#
+                ),
+            );
+        }
+        let manifest: Manifest = toml::from_str(
+            r#"
+            [[packages]]
+            project = "sync/prebuilt"
+            name = "glabu"
+            "#,
+        )?;
+        let mut pinned = manifest.packages[0].clone();
+        pinned.version = Some("1.4.0".to_string());
+        for requirement in [&manifest.packages[0], &pinned] {
+            let locked = resolve_requirement(requirement).await?;
+            assert_eq!(locked.resolved_version, "1.4.0");
+            assert_eq!(locked.files[0].sha256, "sha-of-1");
+        }
+        Ok(())
+    }
+}
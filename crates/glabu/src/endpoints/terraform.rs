@@ -0,0 +1,257 @@
+//! Publish modules to the Terraform module registry of a GitLab project.
+//!
+//! The module directory is packed into a tar.gz, leaving out what `.terraformignore`
+//! excludes (gitignore syntax), or `.git/` and `.terraform/` when there is no such file.
+//! See gitlab api doc: https://docs.gitlab.com/user/packages/terraform_module_registry/
+
+use super::packages::{PackageType, ProjectPackageListOp, ProjectPackageListOrderBy};
+use super::setup::{gitlab_api_url, gitlab_token, httpclient};
+use crate::endpoints::PrintOutput;
+use crate::models::{PackageInfo, SortDirection};
+use flate2::Compression;
+use flate2::write::GzEncoder;
+use regex::Regex;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::path::Path;
+
+const IGNORE_FILE: &str = ".terraformignore";
+const DEFAULT_IGNORE: &str = ".git/\n.terraform/\n";
+
+#[derive(Debug, Clone)]
+struct IgnoreRule {
+    regex: Regex,
+    negated: bool,
+    dir_only: bool,
+}
+
+/// Rules of a `.terraformignore` file, matched against paths relative to the module.
+#[derive(Debug, Clone)]
+pub struct TerraformIgnore {
+    rules: Vec<IgnoreRule>,
+}
+
+impl TerraformIgnore {
+    pub fn parse(content: &str) -> Result<Self, regex::Error> {
+        let mut rules = vec![];
+        for line in content.lines() {
+            let line = line.trim_end();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (negated, pattern) = match line.strip_prefix('!') {
+                Some(pattern) => (true, pattern),
+                None => (false, line),
+            };
+            let dir_only = pattern.ends_with('/');
+            let pattern = pattern.trim_end_matches('/');
+            // like gitignore, a pattern with a slash is relative to the module root
+            let anchored = pattern.contains('/');
+            let pattern = pattern.trim_start_matches('/');
+            let prefix = if anchored { "^" } else { "^(.*/)?" };
+            rules.push(IgnoreRule {
+                regex: Regex::new(&format!("{}{}$", prefix, glob_to_regex(pattern)))?,
+                negated,
+                dir_only,
+            });
+        }
+        Ok(Self { rules })
+    }
+
+    /// Reads the `.terraformignore` of a module, or the default rules.
+    pub fn for_module(dir: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        let ignore_file = dir.join(IGNORE_FILE);
+        let content = match ignore_file.is_file() {
+            true => std::fs::read_to_string(ignore_file)?,
+            false => DEFAULT_IGNORE.to_string(),
+        };
+        Ok(Self::parse(&content)?)
+    }
+
+    /// Whether a path (with `/` separators) is ignored, the last matching rule wins.
+    pub fn is_ignored(&self, path: &str, is_dir: bool) -> bool {
+        let mut ignored = false;
+        for rule in &self.rules {
+            if (!rule.dir_only || is_dir) && rule.regex.is_match(path) {
+                ignored = !rule.negated;
+            }
+        }
+        ignored
+    }
+}
+
+fn glob_to_regex(glob: &str) -> String {
+    let mut regex = String::new();
+    let mut chars = glob.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '*' if chars.peek() == Some(&'*') => {
+                chars.next();
+                if chars.peek() == Some(&'/') {
+                    chars.next();
+                    regex.push_str("(.*/)?");
+                } else {
+                    regex.push_str(".*");
+                }
+            }
+            '*' => regex.push_str("[^/]*"),
+            '?' => regex.push_str("[^/]"),
+            c => regex.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    regex
+}
+
+/// Packs a module directory into a tar.gz, entries in sorted order.
+/// Ignored directories are not descended into.
+pub fn package_module(dir: &Path) -> Result<(Vec<u8>, Vec<String>), Box<dyn std::error::Error>> {
+    let ignore = TerraformIgnore::for_module(dir)?;
+    let mut builder = tar::Builder::new(GzEncoder::new(vec![], Compression::default()));
+    builder.follow_symlinks(false);
+    let mut files = vec![];
+    let mut pending = vec![dir.to_path_buf()];
+    while let Some(current) = pending.pop() {
+        let mut entries = std::fs::read_dir(&current)?.collect::<Result<Vec<_>, _>>()?;
+        entries.sort_by_key(|e| e.file_name());
+        for entry in entries.into_iter().rev() {
+            let path = entry.path();
+            let relative = path
+                .strip_prefix(dir)?
+                .components()
+                .map(|c| c.as_os_str().to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/");
+            let is_dir = entry.file_type()?.is_dir();
+            if ignore.is_ignored(&relative, is_dir) {
+                continue;
+            }
+            if is_dir {
+                builder.append_dir(&relative, &path)?;
+                pending.push(path);
+            } else {
+                builder.append_path_with_name(&path, &relative)?;
+                files.push(relative);
+            }
+        }
+    }
+    let archive = builder.into_inner()?.finish()?;
+    files.sort();
+    Ok((archive, files))
+}
+
+/// Result of publishing a module.
+#[derive(Debug, Serialize)]
+pub struct TerraformModuleOutput {
+    pub name: String,
+    pub system: String,
+    pub version: String,
+    pub files: Vec<String>,
+    pub size: usize,
+    pub sha256: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct TerraformModuleOp {
+    ///  Your project ID or URL-encoded path
+    pub project_id: String,
+}
+
+impl TerraformModuleOp {
+    pub fn new(project_id: &str) -> Self {
+        Self {
+            project_id: project_id.to_string(),
+        }
+    }
+
+    pub async fn publish(
+        &self,
+        dir: &Path,
+        name: &str,
+        system: &str,
+        version: &str,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let (archive, files) = package_module(dir)?;
+        if files.is_empty() {
+            return Err(format!("No files to publish in {}", dir.display()).into());
+        }
+        let size = archive.len();
+        let sha256 = hex::encode(Sha256::digest(&archive));
+        let url = gitlab_api_url(&format!(
+            "/projects/{}/packages/terraform/modules/{}/{}/{}/file",
+            &self.project_id, name, system, version
+        ))?;
+        eprintln!(
+            "Publishing {}/{} {} ({} files)",
+            name,
+            system,
+            version,
+            files.len()
+        );
+        let response = httpclient()
+            .put(url)
+            .header("Private-Token", gitlab_token())
+            .body(archive)
+            .send()
+            .await?;
+        let status = response.status();
+        if status != 201 {
+            return Err(format!(
+                "Upload failed with status: {}, and message: {}",
+                status,
+                response.text().await?
+            )
+            .into());
+        }
+        let msg = PrintOutput {
+            status: "ok".to_string(),
+            output: TerraformModuleOutput {
+                name: name.to_string(),
+                system: system.to_string(),
+                version: version.to_string(),
+                files,
+                size,
+                sha256,
+            },
+        };
+        println!("{}", serde_json::to_string_pretty(&msg)?);
+        Ok(())
+    }
+
+    /// Lists the published module versions, newest first.
+    /// Module packages are named `<name>/<system>`.
+    pub async fn list(
+        &self,
+        name: Option<String>,
+    ) -> Result<Vec<PackageInfo>, Box<dyn std::error::Error>> {
+        ProjectPackageListOp::new(&self.project_id)
+            .package_type(Some(PackageType::TerraformModule))
+            .package_name(name)
+            .order_by(Some(ProjectPackageListOrderBy::CreatedAt))
+            .sort(Some(SortDirection::Desc))
+            .list_all()
+            .await
+    }
+}
+
+#[cfg(test)]
+mod terraform_tests {
+    use super::*;
+
+    #[test]
+    fn test_terraform_ignore() {
+        let ignore = TerraformIgnore::parse(
+            "# comment\n.git/\n*.tfstate*\n/examples/\n**/secrets/*.tfvars\n!keep.tfstate\n",
+        )
+        .unwrap();
+        assert!(ignore.is_ignored(".git", true));
+        assert!(!ignore.is_ignored(".git", false));
+        assert!(ignore.is_ignored("terraform.tfstate", false));
+        assert!(ignore.is_ignored("nested/terraform.tfstate.backup", false));
+        assert!(!ignore.is_ignored("keep.tfstate", false));
+        assert!(ignore.is_ignored("examples", true));
+        assert!(!ignore.is_ignored("modules/examples", true));
+        assert!(ignore.is_ignored("secrets/prod.tfvars", false));
+        assert!(ignore.is_ignored("env/secrets/prod.tfvars", false));
+        assert!(!ignore.is_ignored("main.tf", false));
+    }
+}
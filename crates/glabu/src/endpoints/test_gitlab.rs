@@ -0,0 +1,135 @@
+//! A stand-in GitLab for tests, answering API calls with canned responses.
+//!
+//! There is one server per test binary, `GITLAB_HOST` is pointed at it on first use.
+//! Tests share it, so each test registers its responses under a project of its own.
+
+use super::setup::set_test_instance;
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Mutex, OnceLock};
+
+/// A request the stand-in received.
+#[derive(Debug, Clone)]
+pub struct Request {
+    pub method: String,
+    /// Path without the query, still URL-encoded
+    pub path: String,
+    pub body: Vec<u8>,
+}
+
+#[derive(Default)]
+struct State {
+    /// Status and body by method and path
+    responses: HashMap<(String, String), (u16, Vec<u8>)>,
+    requests: Vec<Request>,
+}
+
+fn state() -> &'static Mutex<State> {
+    static STATE: OnceLock<Mutex<State>> = OnceLock::new();
+    STATE.get_or_init(Default::default)
+}
+
+/// Starts the stand-in if needed and returns its url.
+pub fn host() -> &'static str {
+    static HOST: OnceLock<String> = OnceLock::new();
+    HOST.get_or_init(|| {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let host = format!("http://{}", listener.local_addr().unwrap());
+        set_test_instance(&host, "test-token");
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                std::thread::spawn(move || handle(stream.unwrap()));
+            }
+        });
+        host
+    })
+}
+
+/// Answers `method` requests for the api `path` (e.g. `/projects/1/packages`) with `body`.
+pub fn respond(method: &str, path: &str, status: u16, body: impl Into<Vec<u8>>) {
+    host();
+    state().lock().unwrap().responses.insert(
+        (method.to_string(), format!("/api/v4{}", path)),
+        (status, body.into()),
+    );
+}
+
+/// Requests received for api paths starting with `prefix`.
+pub fn requests(prefix: &str) -> Vec<Request> {
+    let prefix = format!("/api/v4{}", prefix);
+    state()
+        .lock()
+        .unwrap()
+        .requests
+        .iter()
+        .filter(|r| r.path.starts_with(&prefix))
+        .cloned()
+        .collect()
+}
+
+fn handle(mut stream: TcpStream) {
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let mut line = String::new();
+    reader.read_line(&mut line).unwrap();
+    let mut parts = line.split_whitespace();
+    let method = parts.next().unwrap_or_default().to_string();
+    let target = parts.next().unwrap_or_default();
+    let path = target.split('?').next().unwrap_or_default().to_string();
+    let mut content_length = 0;
+    let mut chunked = false;
+    loop {
+        let mut header = String::new();
+        reader.read_line(&mut header).unwrap();
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(": ") {
+            match name.to_ascii_lowercase().as_str() {
+                "content-length" => content_length = value.parse().unwrap(),
+                "transfer-encoding" => chunked = value.eq_ignore_ascii_case("chunked"),
+                _ => {}
+            }
+        }
+    }
+    // read the whole body before answering, the client may still be sending it
+    let mut body = vec![];
+    if chunked {
+        loop {
+            let mut size = String::new();
+            reader.read_line(&mut size).unwrap();
+            let size = usize::from_str_radix(size.trim_end(), 16).unwrap();
+            let mut chunk = vec![0; size + 2];
+            reader.read_exact(&mut chunk).unwrap();
+            if size == 0 {
+                break;
+            }
+            body.extend_from_slice(&chunk[..size]);
+        }
+    } else {
+        body.resize(content_length, 0);
+        reader.read_exact(&mut body).unwrap();
+    }
+
+    let (status, content) = {
+        let mut state = state().lock().unwrap();
+        state.requests.push(Request {
+            method: method.clone(),
+            path: path.clone(),
+            body,
+        });
+        state
+            .responses
+            .get(&(method, path))
+            .cloned()
+            .unwrap_or((404, b"{\"message\":\"404 Not Found\"}".to_vec()))
+    };
+    let head = format!(
+        "HTTP/1.1 {} Stand-in\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        status,
+        content.len()
+    );
+    stream.write_all(head.as_bytes()).unwrap();
+    stream.write_all(&content).unwrap();
+}
@@ -0,0 +1,412 @@
+//! Storage usage of packages in a project or group.
+//!
+//! Sizes of all package files (including repeated uploads of the same file name, which
+//! also count against the quota) are summed per package version, per package name and per month.
+
+use super::packages::{GroupPackageListOp, PackageType, ProjectPackageListOp};
+use crate::endpoints::PrintOutput;
+use clap::ValueEnum;
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// How to print a report.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ReportFormat {
+    Table,
+    Csv,
+    Json,
+}
+
+/// Storage used by one package version.
+#[derive(Debug, Clone, Serialize, PartialEq, Eq)]
+pub struct VersionUsage {
+    pub project: String,
+    pub name: String,
+    pub version: String,
+    pub files: usize,
+    /// Bytes
+    pub size: u64,
+    pub created_at: Option<String>,
+}
+
+/// Storage used by all versions of one package.
+#[derive(Debug, Clone, Serialize, PartialEq, Eq)]
+pub struct NameUsage {
+    pub project: String,
+    pub name: String,
+    pub versions: usize,
+    pub files: usize,
+    /// Bytes
+    pub size: u64,
+}
+
+/// Storage added in one month, by the `created_at` of the files.
+#[derive(Debug, Clone, Serialize, PartialEq, Eq)]
+pub struct MonthlyUsage {
+    /// `YYYY-MM`
+    pub month: String,
+    pub added: u64,
+    /// Bytes used at the end of the month
+    pub total: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PackageUsageOutput {
+    pub total_size: u64,
+    pub versions: usize,
+    /// Largest packages first
+    pub packages: Vec<NameUsage>,
+    /// Largest versions first
+    pub largest: Vec<VersionUsage>,
+    pub growth: Vec<MonthlyUsage>,
+}
+
+#[derive(Debug, Clone)]
+pub struct PackageUsageOp {
+    /// Project or group ID, or URL-encoded path
+    pub id: String,
+    /// Whether `id` is a group, including its subgroups
+    pub group: bool,
+    pub package_type: Option<PackageType>,
+    /// Number of versions to show, all when 0
+    pub top: usize,
+    pub format: ReportFormat,
+    /// Show the growth per month instead of the largest versions in table and csv output
+    pub growth: bool,
+    /// Show the totals per package name instead of the largest versions in table and csv output
+    pub by_name: bool,
+}
+
+impl PackageUsageOp {
+    pub fn new(id: &str, group: bool) -> Self {
+        Self {
+            id: id.to_string(),
+            group,
+            package_type: None,
+            top: 20,
+            format: ReportFormat::Table,
+            growth: false,
+            by_name: false,
+        }
+    }
+
+    pub fn package_type(mut self, package_type: Option<PackageType>) -> Self {
+        self.package_type = package_type;
+        self
+    }
+    pub fn top(mut self, top: usize) -> Self {
+        self.top = top;
+        self
+    }
+    pub fn format(mut self, format: ReportFormat) -> Self {
+        self.format = format;
+        self
+    }
+    pub fn growth(mut self, growth: bool) -> Self {
+        self.growth = growth;
+        self
+    }
+    pub fn by_name(mut self, by_name: bool) -> Self {
+        self.by_name = by_name;
+        self
+    }
+
+    pub async fn run(self) -> Result<(), Box<dyn std::error::Error>> {
+        let packages = if self.group {
+            GroupPackageListOp::new(&self.id)
+                .package_type(self.package_type.clone())
+                .list_all()
+                .await?
+        } else {
+            ProjectPackageListOp::new(&self.id)
+                .package_type(self.package_type.clone())
+                .list_all()
+                .await?
+        };
+        let default_project = urlencoding::decode(&self.id)?.into_owned();
+        let mut versions = vec![];
+        let mut files = vec![];
+        for package in packages {
+            let project_id = match package.project_id {
+                Some(id) => id.to_string(),
+                None => self.id.clone(),
+            };
+            let package_files = ProjectPackageListOp::new(&project_id)
+                .package_files(&package)
+                .await?;
+            versions.push(VersionUsage {
+                project: package
+                    .project_path
+                    .clone()
+                    .unwrap_or_else(|| default_project.clone()),
+                name: package.name.clone(),
+                version: package.version.clone(),
+                files: package_files.len(),
+                size: package_files.iter().filter_map(|f| f.size).sum(),
+                created_at: package.created_at.clone(),
+            });
+            files.extend(
+                package_files
+                    .into_iter()
+                    .map(|f| (f.created_at, f.size.unwrap_or(0))),
+            );
+        }
+
+        versions.sort_by_key(|v| std::cmp::Reverse(v.size));
+        let output = PackageUsageOutput {
+            total_size: versions.iter().map(|v| v.size).sum(),
+            versions: versions.len(),
+            packages: name_usage(&versions),
+            largest: match self.top {
+                0 => versions,
+                top => versions.into_iter().take(top).collect(),
+            },
+            growth: monthly_growth(&files),
+        };
+        match self.format {
+            ReportFormat::Json => {
+                let msg = PrintOutput {
+                    status: "ok".to_string(),
+                    output,
+                };
+                println!("{}", serde_json::to_string_pretty(&msg)?);
+            }
+            ReportFormat::Csv => print!("{}", self.rows(&output, false).csv()),
+            ReportFormat::Table => {
+                println!(
+                    "Total: {} in {} versions\n",
+                    format_size(output.total_size),
+                    output.versions
+                );
+                print!("{}", self.rows(&output, true).table());
+            }
+        }
+        Ok(())
+    }
+
+    fn rows(&self, output: &PackageUsageOutput, human: bool) -> Rows {
+        let size = |size: u64| {
+            if human {
+                format_size(size)
+            } else {
+                size.to_string()
+            }
+        };
+        if self.growth {
+            return Rows {
+                headers: vec!["month", "added", "total"],
+                rows: output
+                    .growth
+                    .iter()
+                    .map(|m| vec![m.month.clone(), size(m.added), size(m.total)])
+                    .collect(),
+            };
+        }
+        if self.by_name {
+            return Rows {
+                headers: vec!["project", "name", "versions", "files", "size"],
+                rows: output
+                    .packages
+                    .iter()
+                    .map(|n| {
+                        vec![
+                            n.project.clone(),
+                            n.name.clone(),
+                            n.versions.to_string(),
+                            n.files.to_string(),
+                            size(n.size),
+                        ]
+                    })
+                    .collect(),
+            };
+        }
+        Rows {
+            headers: vec!["project", "name", "version", "files", "size", "created_at"],
+            rows: output
+                .largest
+                .iter()
+                .map(|v| {
+                    vec![
+                        v.project.clone(),
+                        v.name.clone(),
+                        v.version.clone(),
+                        v.files.to_string(),
+                        size(v.size),
+                        v.created_at.clone().unwrap_or_default(),
+                    ]
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Sums the usage of versions per project and package name, largest first.
+pub fn name_usage(versions: &[VersionUsage]) -> Vec<NameUsage> {
+    let mut by_name: BTreeMap<(&str, &str), NameUsage> = BTreeMap::new();
+    for v in versions {
+        let usage = by_name
+            .entry((&v.project, &v.name))
+            .or_insert_with(|| NameUsage {
+                project: v.project.clone(),
+                name: v.name.clone(),
+                versions: 0,
+                files: 0,
+                size: 0,
+            });
+        usage.versions += 1;
+        usage.files += v.files;
+        usage.size += v.size;
+    }
+    let mut names: Vec<NameUsage> = by_name.into_values().collect();
+    names.sort_by_key(|n| std::cmp::Reverse(n.size));
+    names
+}
+
+/// Sums file sizes per month of `created_at`, with the running total, oldest month first.
+pub fn monthly_growth(files: &[(String, u64)]) -> Vec<MonthlyUsage> {
+    let mut by_month: BTreeMap<String, u64> = BTreeMap::new();
+    for (created_at, size) in files {
+        let month = created_at.get(..7).unwrap_or(created_at);
+        *by_month.entry(month.to_string()).or_default() += size;
+    }
+    let mut total = 0;
+    by_month
+        .into_iter()
+        .map(|(month, added)| {
+            total += added;
+            MonthlyUsage {
+                month,
+                added,
+                total,
+            }
+        })
+        .collect()
+}
+
+/// Formats a byte count with binary units, e.g. `1.5 MiB`.
+pub fn format_size(size: u64) -> String {
+    const UNITS: &[&str] = &["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut value = size as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    match unit {
+        0 => format!("{} B", size),
+        _ => format!("{:.1} {}", value, UNITS[unit]),
+    }
+}
+
+/// Rows of a report, printable as an aligned table or as CSV.
+struct Rows {
+    headers: Vec<&'static str>,
+    rows: Vec<Vec<String>>,
+}
+
+impl Rows {
+    fn table(&self) -> String {
+        let mut widths: Vec<usize> = self.headers.iter().map(|h| h.len()).collect();
+        for row in &self.rows {
+            for (width, cell) in widths.iter_mut().zip(row) {
+                *width = (*width).max(cell.chars().count());
+            }
+        }
+        let line = |cells: Vec<String>| {
+            let cells: Vec<String> = cells
+                .iter()
+                .zip(&widths)
+                .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+                .collect();
+            format!("{}\n", cells.join("  ").trim_end())
+        };
+        let headers = self.headers.iter().map(|h| h.to_uppercase()).collect();
+        let mut table = line(headers);
+        for row in &self.rows {
+            table.push_str(&line(row.clone()));
+        }
+        table
+    }
+
+    fn csv(&self) -> String {
+        let line = |cells: Vec<String>| {
+            let cells: Vec<String> = cells.iter().map(|cell| csv_field(cell)).collect();
+            format!("{}\n", cells.join(","))
+        };
+        let mut csv = line(self.headers.iter().map(|h| h.to_string()).collect());
+        for row in &self.rows {
+            csv.push_str(&line(row.clone()));
+        }
+        csv
+    }
+}
+
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+#[cfg(test)]
+mod usage_tests {
+    use super::*;
+
+    #[test]
+    fn test_monthly_growth() {
+        let files = vec![
+            ("2025-02-03T10:00:00.000Z".to_string(), 10),
+            ("2025-01-30T10:00:00.000Z".to_string(), 5),
+            ("2025-02-28T10:00:00.000Z".to_string(), 1),
+        ];
+        let growth = monthly_growth(&files);
+        let months: Vec<_> = growth
+            .iter()
+            .map(|m| (m.month.as_str(), m.added, m.total))
+            .collect();
+        assert_eq!(months, vec![("2025-01", 5, 5), ("2025-02", 11, 16)]);
+    }
+
+    #[test]
+    fn test_name_usage() {
+        let version = |project: &str, name: &str, version: &str, size| VersionUsage {
+            project: project.to_string(),
+            name: name.to_string(),
+            version: version.to_string(),
+            files: 2,
+            size,
+            created_at: None,
+        };
+        let versions = vec![
+            version("a/b", "glabu", "1.0.0", 10),
+            version("a/b", "tool", "1.0.0", 15),
+            version("a/b", "glabu", "1.1.0", 20),
+            version("a/c", "glabu", "1.0.0", 1),
+        ];
+        let names: Vec<_> = name_usage(&versions)
+            .into_iter()
+            .map(|n| (n.project, n.name, n.versions, n.files, n.size))
+            .collect();
+        assert_eq!(
+            names,
+            vec![
+                ("a/b".to_string(), "glabu".to_string(), 2, 4, 30),
+                ("a/b".to_string(), "tool".to_string(), 1, 2, 15),
+                ("a/c".to_string(), "glabu".to_string(), 1, 2, 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_report_rows() {
+        assert_eq!(format_size(512), "512 B");
+        assert_eq!(format_size(1536), "1.5 KiB");
+        let rows = Rows {
+            headers: vec!["name", "size"],
+            rows: vec![vec!["a,b".to_string(), "1".to_string()]],
+        };
+        assert_eq!(rows.csv(), "name,size\n\"a,b\",1\n");
+        assert_eq!(rows.table(), "NAME  SIZE\na,b   1\n");
+    }
+}
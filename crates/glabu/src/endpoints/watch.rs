@@ -0,0 +1,230 @@
+//! Watch a generic package and react when a new version appears.
+//!
+//! The package list is polled with `If-None-Match`, so an unchanged registry costs a
+//! single 304 response. The polling interval doubles while nothing changes (or polling
+//! fails), up to a maximum, and is reset whenever a new version is handled.
+
+use super::packages::{
+    GenericPackageOp, PackageDownloadOutput, PackageType, ProjectPackageListOp,
+    ProjectPackageListOrderBy, VersionStrategy,
+};
+use crate::models::SortDirection;
+use std::path::PathBuf;
+use std::time::Duration;
+use tokio::process::Command;
+
+#[derive(Debug, Clone)]
+pub struct PackageWatchOp {
+    ///  Your project ID or URL-encoded path
+    pub project_id: String,
+    pub package_name: String,
+    /// How to pick the latest version
+    pub version_strategy: VersionStrategy,
+    /// Only download files matching this regex
+    pub pattern: Option<String>,
+    /// Directory to download new versions into
+    pub output_dir: PathBuf,
+    /// Shell command to run after a new version was downloaded
+    pub hook: Option<String>,
+    /// Version that is already deployed, the latest version at startup when not set
+    pub since: Option<String>,
+    pub interval: Duration,
+    pub max_interval: Duration,
+}
+
+impl PackageWatchOp {
+    pub fn new(project_id: &str, package_name: &str, output_dir: PathBuf) -> Self {
+        Self {
+            project_id: project_id.to_string(),
+            package_name: package_name.to_string(),
+            version_strategy: VersionStrategy::default(),
+            pattern: None,
+            output_dir,
+            hook: None,
+            since: None,
+            interval: Duration::from_secs(60),
+            max_interval: Duration::from_secs(900),
+        }
+    }
+
+    pub fn version_strategy(mut self, version_strategy: VersionStrategy) -> Self {
+        self.version_strategy = version_strategy;
+        self
+    }
+    pub fn pattern(mut self, pattern: Option<String>) -> Self {
+        self.pattern = pattern;
+        self
+    }
+    pub fn hook(mut self, hook: Option<String>) -> Self {
+        self.hook = hook;
+        self
+    }
+    pub fn since(mut self, since: Option<String>) -> Self {
+        self.since = since;
+        self
+    }
+    pub fn interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+    pub fn max_interval(mut self, max_interval: Duration) -> Self {
+        self.max_interval = max_interval.max(self.interval);
+        self
+    }
+
+    /// Polls forever.
+    pub async fn run(self) -> Result<(), Box<dyn std::error::Error>> {
+        if !self.output_dir.is_dir() {
+            return Err(format!(
+                "OutputNotFound: directory {} does not exist",
+                self.output_dir.display()
+            )
+            .into());
+        }
+        let list_op = ProjectPackageListOp::new(&self.project_id)
+            .package_name(Some(self.package_name.clone()))
+            .package_type(Some(PackageType::Generic))
+            .order_by(Some(ProjectPackageListOrderBy::CreatedAt))
+            .sort(Some(SortDirection::Desc))
+            .per_page(Some(20));
+        let mut etag = None;
+        let mut current = self.since.clone();
+        let mut delay = self.interval;
+        loop {
+            match self.poll(&list_op, &mut etag, &mut current).await {
+                Ok(true) => delay = self.interval,
+                Ok(false) => delay = next_delay(delay, self.max_interval),
+                Err(e) => {
+                    eprintln!("Polling {} failed: {}", &self.package_name, e);
+                    delay = next_delay(delay, self.max_interval);
+                }
+            }
+            eprintln!("Next check in {}s", delay.as_secs());
+            tokio::time::sleep(delay).await;
+        }
+    }
+
+    /// Checks for a new version once, returns whether one was handled.
+    ///
+    /// `etag` and `current` are only updated once the new version has been downloaded,
+    /// so a failed download is retried on the next poll.
+    async fn poll(
+        &self,
+        list_op: &ProjectPackageListOp,
+        etag: &mut Option<String>,
+        current: &mut Option<String>,
+    ) -> Result<bool, Box<dyn std::error::Error>> {
+        let Some((_, new_etag)) = list_op.list_if_changed(etag.as_deref()).await? else {
+            return Ok(false);
+        };
+        let package = list_op.resolve(&self.version_strategy).await?;
+        let changed = match version_change(current.as_deref(), &package.version) {
+            VersionChange::First => {
+                eprintln!(
+                    "Watching {} from version {}",
+                    &self.package_name, &package.version
+                );
+                false
+            }
+            VersionChange::Unchanged => false,
+            VersionChange::New(version) => {
+                eprintln!(
+                    "New version of {}: {} -> {}",
+                    &self.package_name, version, &package.version
+                );
+                let downloaded = GenericPackageOp::new(&self.project_id, &self.package_name, "")
+                    .package_version(Some(package.version.clone()))
+                    .download_files(
+                        self.output_dir.clone(),
+                        Some(self.pattern.clone().unwrap_or(".*".to_string())),
+                        None,
+                    )
+                    .await?;
+                if let Some(hook) = &self.hook {
+                    self.run_hook(hook, version, &downloaded).await;
+                }
+                true
+            }
+        };
+        *etag = new_etag;
+        *current = Some(package.version);
+        Ok(changed)
+    }
+
+    /// Runs the hook with `sh -c`. A failing hook is reported but does not stop watching.
+    ///
+    /// The hook gets `GLABU_PACKAGE_NAME`, `GLABU_PACKAGE_VERSION`, `GLABU_PREVIOUS_VERSION`,
+    /// `GLABU_OUTPUT_DIR` and `GLABU_PACKAGE_FILES` (one path per line) in its environment.
+    async fn run_hook(&self, hook: &str, previous: &str, downloaded: &PackageDownloadOutput) {
+        let files = downloaded
+            .files
+            .iter()
+            .chain(downloaded.extracted.iter().flatten())
+            .cloned()
+            .collect::<Vec<_>>()
+            .join("\n");
+        let status = Command::new("sh")
+            .arg("-c")
+            .arg(hook)
+            .env("GLABU_PACKAGE_NAME", &downloaded.package_name)
+            .env("GLABU_PACKAGE_VERSION", &downloaded.package_version)
+            .env("GLABU_PREVIOUS_VERSION", previous)
+            .env("GLABU_OUTPUT_DIR", &self.output_dir)
+            .env("GLABU_PACKAGE_FILES", files)
+            .status()
+            .await;
+        match status {
+            Ok(status) if status.success() => {}
+            Ok(status) => eprintln!("Hook failed with {}", status),
+            Err(e) => eprintln!("Hook could not be started: {}", e),
+        }
+    }
+}
+
+/// How the version resolved on a poll relates to the current one.
+#[derive(Debug, PartialEq, Eq)]
+enum VersionChange<'a> {
+    /// Nothing is known yet, the resolved version becomes the current one
+    First,
+    Unchanged,
+    /// A new version, with the previous one
+    New(&'a str),
+}
+
+fn version_change<'a>(current: Option<&'a str>, resolved: &str) -> VersionChange<'a> {
+    match current {
+        None => VersionChange::First,
+        Some(version) if version == resolved => VersionChange::Unchanged,
+        Some(version) => VersionChange::New(version),
+    }
+}
+
+/// Doubles the polling delay, up to `max`.
+fn next_delay(delay: Duration, max: Duration) -> Duration {
+    (delay * 2).min(max)
+}
+
+#[cfg(test)]
+mod watch_tests {
+    use super::*;
+
+    #[test]
+    fn test_version_change() {
+        assert_eq!(version_change(None, "1.0"), VersionChange::First);
+        assert_eq!(version_change(Some("1.0"), "1.0"), VersionChange::Unchanged);
+        assert_eq!(
+            version_change(Some("1.0"), "1.1"),
+            VersionChange::New("1.0")
+        );
+    }
+
+    #[test]
+    fn test_next_delay() {
+        let max = Duration::from_secs(900);
+        assert_eq!(
+            next_delay(Duration::from_secs(60), max),
+            Duration::from_secs(120)
+        );
+        assert_eq!(next_delay(Duration::from_secs(600), max), max);
+    }
+}
@@ -1,3 +1,6 @@
+pub mod archive;
 pub mod cli;
 pub mod endpoints;
 pub mod models;
+pub mod oci;
+pub mod signing;
@@ -0,0 +1,59 @@
+use serde::{Deserialize, Serialize};
+
+/// Package requirements read from `glabu.toml` or `glabu.yaml`.
+///
+/// ```toml
+/// target_dir = "tools"
+///
+/// [[packages]]
+/// project = "puterize/prebuilt"
+/// name = "glabu"
+/// strategy = "semver-max"
+/// files = "glabu-x86_64"
+/// ```
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Manifest {
+    /// Directory the package files are downloaded into, relative to the manifest
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target_dir: Option<String>,
+    pub packages: Vec<ManifestPackage>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ManifestPackage {
+    /// Full path to the project, for example: owner/project
+    pub project: String,
+    /// Name of the package
+    pub name: String,
+    /// Exact version of the package, takes precedence over `strategy`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    /// created-at, semver-max, or a semver requirement such as ^1.4
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub strategy: Option<String>,
+    /// Filename regex to filter files, all files of the version when absent
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub files: Option<String>,
+}
+
+/// Exact versions and checksums resolved from a [`Manifest`], stored in `glabu.lock`.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Lockfile {
+    #[serde(default)]
+    pub packages: Vec<LockedPackage>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LockedPackage {
+    /// The manifest entry this package was resolved from
+    pub requirement: ManifestPackage,
+    /// The concrete version that was resolved
+    pub resolved_version: String,
+    pub files: Vec<LockedFile>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LockedFile {
+    pub file_name: String,
+    pub sha256: String,
+}
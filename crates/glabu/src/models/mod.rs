@@ -1,13 +1,19 @@
 mod group;
+mod helm;
+mod manifest;
 mod package_list_item;
 mod project;
 mod project_search;
+mod registry;
 mod release;
 mod user;
 pub use group::*;
+pub use helm::*;
+pub use manifest::*;
 pub use package_list_item::*;
 pub use project::*;
 pub use project_search::*;
+pub use registry::*;
 pub use release::*;
 pub use user::*;
 
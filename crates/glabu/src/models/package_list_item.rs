@@ -1,4 +1,133 @@
 use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+/// Enum for package types.
+/// Types not known to glabu are kept as `Unknown` so that listing never fails
+/// when GitLab adds a new registry.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(from = "String", into = "String")]
+pub enum PackageType {
+    Cargo,
+    Composer,
+    Conan,
+    Conda,
+    Debian,
+    Generic,
+    Golang,
+    Helm,
+    Maven,
+    MlModel,
+    Npm,
+    Nuget,
+    Pypi,
+    Rpm,
+    Rubygems,
+    TerraformModule,
+    Unknown(String),
+}
+
+impl PackageType {
+    /// The types known to glabu, in the order of the API docs.
+    pub const KNOWN: [PackageType; 16] = [
+        PackageType::Cargo,
+        PackageType::Composer,
+        PackageType::Conan,
+        PackageType::Conda,
+        PackageType::Debian,
+        PackageType::Generic,
+        PackageType::Golang,
+        PackageType::Helm,
+        PackageType::Maven,
+        PackageType::MlModel,
+        PackageType::Npm,
+        PackageType::Nuget,
+        PackageType::Pypi,
+        PackageType::Rpm,
+        PackageType::Rubygems,
+        PackageType::TerraformModule,
+    ];
+
+    pub fn as_str(&self) -> &str {
+        match self {
+            PackageType::Cargo => "cargo",
+            PackageType::Composer => "composer",
+            PackageType::Conan => "conan",
+            PackageType::Conda => "conda",
+            PackageType::Debian => "debian",
+            PackageType::Generic => "generic",
+            PackageType::Golang => "golang",
+            PackageType::Helm => "helm",
+            PackageType::Maven => "maven",
+            PackageType::MlModel => "ml_model",
+            PackageType::Npm => "npm",
+            PackageType::Nuget => "nuget",
+            PackageType::Pypi => "pypi",
+            PackageType::Rpm => "rpm",
+            PackageType::Rubygems => "rubygems",
+            PackageType::TerraformModule => "terraform_module",
+            PackageType::Unknown(s) => s,
+        }
+    }
+}
+
+impl From<String> for PackageType {
+    fn from(s: String) -> Self {
+        match s.as_str() {
+            "cargo" => PackageType::Cargo,
+            "composer" => PackageType::Composer,
+            "conan" => PackageType::Conan,
+            "conda" => PackageType::Conda,
+            "debian" => PackageType::Debian,
+            "generic" => PackageType::Generic,
+            "golang" => PackageType::Golang,
+            "helm" => PackageType::Helm,
+            "maven" => PackageType::Maven,
+            "ml_model" => PackageType::MlModel,
+            "npm" => PackageType::Npm,
+            "nuget" => PackageType::Nuget,
+            "pypi" => PackageType::Pypi,
+            "rpm" => PackageType::Rpm,
+            "rubygems" => PackageType::Rubygems,
+            "terraform_module" => PackageType::TerraformModule,
+            _ => PackageType::Unknown(s),
+        }
+    }
+}
+
+impl From<PackageType> for String {
+    fn from(t: PackageType) -> Self {
+        t.as_str().to_string()
+    }
+}
+
+impl FromStr for PackageType {
+    type Err = String;
+
+    /// Accepts both the API spelling (`ml_model`) and the cli spelling (`ml-model`).
+    /// Unlike deserializing, unknown types are an error here, so that a typo is not
+    /// sent to the API as a filter that matches nothing.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match PackageType::from(s.replace('-', "_")) {
+            PackageType::Unknown(_) => Err(format!(
+                "unknown package type {}, expected one of: {}",
+                s,
+                PackageType::KNOWN
+                    .iter()
+                    .map(PackageType::as_str)
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )),
+            package_type => Ok(package_type),
+        }
+    }
+}
+
+impl fmt::Display for PackageType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct PackageInfo {
@@ -8,8 +137,13 @@ pub struct PackageInfo {
     pub tags: Vec<String>,
     pub created_at: Option<String>,
     pub last_downloaded_at: Option<String>,
-    pub package_type: Option<String>,
+    pub package_type: Option<PackageType>,
     pub status: Option<String>,
+    // only present when listing packages of a group
+    #[serde(default)]
+    pub project_id: Option<u64>,
+    #[serde(default)]
+    pub project_path: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -30,7 +30,7 @@ pub struct Project {
     pub visibility: String,
     pub owner: Option<Owner>,
     // pub resolve_outdated_diff_discussions: bool,
-    // pub container_expiration_policy: Option<ContainerExpirationPolicy>,
+    pub container_expiration_policy: Option<ContainerExpirationPolicy>,
     // pub repository_object_format: String,
     // pub issues_enabled: bool,
     // pub merge_requests_enabled: bool,
@@ -153,16 +153,35 @@ pub struct Owner {
     // pub web_url: String,
 }
 
-// #[derive(Debug, Serialize, Deserialize)]
-// pub struct ContainerExpirationPolicy {
-//     pub cadence: String,
-//     pub enabled: bool,
-//     pub keep_n: i64,
-//     pub older_than: String,
-//     pub name_regex: String,
-//     pub name_regex_keep: Option<String>,
-//     pub next_run_at: String,
-// }
+/// The cleanup policy of the container registry of a project.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ContainerExpirationPolicy {
+    pub cadence: String,
+    pub enabled: bool,
+    pub keep_n: Option<u32>,
+    pub older_than: Option<String>,
+    pub name_regex: Option<String>,
+    pub name_regex_keep: Option<String>,
+    pub next_run_at: Option<String>,
+}
+
+/// Changes to the cleanup policy, sent as `container_expiration_policy_attributes`.
+/// Fields left out are not changed.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ContainerExpirationPolicyAttributes {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cadence: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub enabled: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub keep_n: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub older_than: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name_regex: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name_regex_keep: Option<String>,
+}
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ProjectPushMirrorPayload {
@@ -0,0 +1,34 @@
+use serde::{Deserialize, Serialize};
+
+/// A repository of the container registry of a project.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RegistryRepository {
+    pub id: u64,
+    /// Empty for the repository at the root of the project
+    pub name: String,
+    pub path: String,
+    pub project_id: u64,
+    pub location: String,
+    pub created_at: Option<String>,
+    pub cleanup_policy_started_at: Option<String>,
+    pub tags_count: Option<u64>,
+}
+
+/// A tag of a registry repository.
+/// The tag list only has name, path and location, the rest comes with the tag details.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RegistryTag {
+    pub name: String,
+    pub path: String,
+    pub location: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub revision: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub short_revision: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub digest: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created_at: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub total_size: Option<u64>,
+}
@@ -1,111 +1,173 @@
 use serde::{Deserialize, Serialize};
-#[derive(Debug, Serialize, Deserialize)]
+
+/// A release of a project.
+/// See https://docs.gitlab.com/api/releases/
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ProjectRelease {
-    name: String,
-    tag_name: String,
-    description: String,
-    created_at: String,
-    released_at: String,
-    upcoming_release: bool,
-    author: Author,
-    commit: Commit,
-    // milestones: Option<Vec<Milestone>>,
-    commit_path: String,
-    tag_path: String,
-    assets: Assets,
-    evidences: Vec<Evidence>,
-    // _links: Links,
+    pub name: Option<String>,
+    pub tag_name: String,
+    pub description: Option<String>,
+    pub created_at: String,
+    pub released_at: Option<String>,
+    #[serde(default)]
+    pub upcoming_release: bool,
+    pub author: Option<ReleaseAuthor>,
+    pub commit: Option<ReleaseCommit>,
+    #[serde(default)]
+    pub milestones: Vec<ReleaseMilestone>,
+    pub commit_path: Option<String>,
+    pub tag_path: Option<String>,
+    #[serde(default)]
+    pub assets: ReleaseAssets,
+    #[serde(default)]
+    pub evidences: Vec<ReleaseEvidence>,
+    #[serde(rename = "_links")]
+    pub links: Option<ReleaseLinks>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
-struct Author {
-    id: u64,
-    username: String,
-    name: String,
-    state: String,
-    locked: bool,
-    avatar_url: String,
-    web_url: String,
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ReleaseAuthor {
+    pub id: u64,
+    pub username: String,
+    pub name: String,
+    pub state: String,
+    pub locked: Option<bool>,
+    pub avatar_url: Option<String>,
+    pub web_url: String,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
-struct Commit {
-    id: String,
-    short_id: String,
-    created_at: String,
-    parent_ids: Vec<String>,
-    title: String,
-    message: String,
-    author_name: String,
-    author_email: String,
-    authored_date: String,
-    committer_name: String,
-    committer_email: String,
-    committed_date: String,
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ReleaseCommit {
+    pub id: String,
+    pub short_id: String,
+    pub created_at: String,
+    #[serde(default)]
+    pub parent_ids: Vec<String>,
+    pub title: String,
+    pub message: String,
+    pub author_name: String,
+    pub author_email: String,
+    pub authored_date: String,
+    pub committer_name: String,
+    pub committer_email: String,
+    pub committed_date: String,
     // trailers: Value,
     // extended_trailers: Value,
-    web_url: String,
+    pub web_url: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ReleaseMilestone {
+    pub id: u64,
+    pub iid: u64,
+    pub project_id: Option<u64>,
+    pub group_id: Option<u64>,
+    pub title: String,
+    pub description: Option<String>,
+    pub state: String,
+    pub created_at: String,
+    pub updated_at: String,
+    pub due_date: Option<String>,
+    pub start_date: Option<String>,
+    pub expired: Option<bool>,
+    pub web_url: String,
+    pub issue_stats: Option<IssueStats>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
-struct Milestone {
-    id: u64,
-    iid: u64,
-    group_id: u64,
-    title: String,
-    description: String,
-    state: String,
-    created_at: String,
-    updated_at: String,
-    due_date: Option<String>,
-    start_date: Option<String>,
-    expired: bool,
-    web_url: String,
-    issue_stats: IssueStats,
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct IssueStats {
+    pub total: u64,
+    pub closed: u64,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
-struct IssueStats {
-    total: u64,
-    closed: u64,
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct ReleaseAssets {
+    pub count: u64,
+    #[serde(default)]
+    pub sources: Vec<ReleaseSource>,
+    #[serde(default)]
+    pub links: Vec<ReleaseLink>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
-struct Assets {
-    count: u64,
-    sources: Vec<Source>,
-    links: Vec<Link>,
+/// An archive of the sources at the tag, e.g. `zip` or `tar.gz`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ReleaseSource {
+    pub format: String,
+    pub url: String,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
-struct Source {
-    format: String,
-    url: String,
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ReleaseLink {
+    pub id: Option<u64>,
+    pub name: String,
+    pub url: String,
+    pub direct_asset_url: Option<String>,
+    pub link_type: Option<ReleaseLinkType>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
-struct Link {
-    id: Option<u64>,
-    name: String,
-    url: String,
-    direct_asset_url: String,
-    link_type: String,
+/// Kind of a release asset link.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ReleaseLinkType {
+    Other,
+    Runbook,
+    Image,
+    Package,
+    /// A type added to GitLab after this version of glabu
+    #[serde(other)]
+    Unknown,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
-struct Evidence {
-    sha: String,
-    filepath: String,
-    collected_at: String,
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ReleaseEvidence {
+    pub sha: String,
+    pub filepath: String,
+    pub collected_at: String,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
-struct Links {
-    closed_issues_url: String,
-    closed_merge_requests_url: String,
-    merged_merge_requests_url: String,
-    // opended_issues_url: String,
-    // opended_merge_requests_url: String,
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ReleaseLinks {
+    pub closed_issues_url: Option<String>,
+    pub closed_merge_requests_url: Option<String>,
+    pub merged_merge_requests_url: Option<String>,
+    pub opened_issues_url: Option<String>,
+    pub opened_merge_requests_url: Option<String>,
     #[serde(rename = "self")]
-    selflink: String,
+    pub selflink: Option<String>,
+}
+
+/// Body of a create release request.
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct ReleaseCreatePayload {
+    pub tag_name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// Commit, branch or tag to create `tag_name` from if it does not exist yet
+    #[serde(rename = "ref", skip_serializing_if = "Option::is_none")]
+    pub git_ref: Option<String>,
+    /// Titles of the milestones
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub milestones: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub released_at: Option<String>,
+    pub assets: ReleaseAssetsPayload,
+}
+
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct ReleaseAssetsPayload {
+    pub links: Vec<ReleaseLinkPayload>,
+}
+
+/// A link to create along with a release.
+#[derive(Serialize, Debug, Clone)]
+pub struct ReleaseLinkPayload {
+    pub name: String,
+    pub url: String,
+    /// Path under the release's permanent link, it must start with `/`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub direct_asset_path: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub link_type: Option<ReleaseLinkType>,
 }
@@ -0,0 +1,654 @@
+//! Push of OCI image layouts to a container registry over the OCI distribution API.
+//!
+//! An image layout is what `podman push --format oci <image> oci:<dir>`, `skopeo copy`
+//! or `buildah` write: an `oci-layout` marker, an `index.json` and content addressed
+//! blobs. Blobs are uploaded first (skipping those the registry already has), then the
+//! image manifests, and finally the tagged manifest or multi-arch index.
+//!
+//! Registries answer an anonymous `GET /v2/` with a `WWW-Authenticate` challenge. For
+//! a `Bearer` challenge (GitLab's `/jwt/auth`) a push token is requested from the realm
+//! with basic auth, a `Basic` challenge takes the credentials directly.
+//! See https://github.com/opencontainers/distribution-spec/blob/main/spec.md
+
+use regex::Regex;
+use reqwest::header::{CONTENT_TYPE, LOCATION, WWW_AUTHENTICATE};
+use reqwest::{Client, RequestBuilder, StatusCode, Url};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+
+pub const MEDIA_TYPE_INDEX: &str = "application/vnd.oci.image.index.v1+json";
+pub const MEDIA_TYPE_MANIFEST: &str = "application/vnd.oci.image.manifest.v1+json";
+const MEDIA_TYPE_DOCKER_LIST: &str = "application/vnd.docker.distribution.manifest.list.v2+json";
+/// Annotation of `index.json` entries holding the tag they were saved under.
+pub const REF_NAME_ANNOTATION: &str = "org.opencontainers.image.ref.name";
+
+/// A reference to content: media type, digest and size.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Descriptor {
+    pub media_type: String,
+    pub digest: String,
+    pub size: u64,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub annotations: BTreeMap<String, String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub platform: Option<Platform>,
+}
+
+impl Descriptor {
+    pub fn is_index(&self) -> bool {
+        self.media_type == MEDIA_TYPE_INDEX || self.media_type == MEDIA_TYPE_DOCKER_LIST
+    }
+
+    pub fn ref_name(&self) -> Option<&str> {
+        self.annotations
+            .get(REF_NAME_ANNOTATION)
+            .map(String::as_str)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Platform {
+    pub architecture: String,
+    pub os: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub variant: Option<String>,
+}
+
+/// An image index, including the `index.json` of a layout.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImageIndex {
+    #[serde(default)]
+    pub media_type: Option<String>,
+    #[serde(default)]
+    pub manifests: Vec<Descriptor>,
+}
+
+/// An image manifest, only the fields needed to find its blobs.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ImageManifest {
+    pub config: Descriptor,
+    #[serde(default)]
+    pub layers: Vec<Descriptor>,
+}
+
+/// An OCI image layout directory.
+#[derive(Debug, Clone)]
+pub struct OciLayout {
+    pub dir: PathBuf,
+    pub index: ImageIndex,
+}
+
+impl OciLayout {
+    pub fn open(dir: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        if !dir.join("oci-layout").is_file() {
+            return Err(format!("InvalidLayout: no oci-layout file in {}", dir.display()).into());
+        }
+        let index = std::fs::read(dir.join("index.json"))?;
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            index: serde_json::from_slice(&index)?,
+        })
+    }
+
+    /// Reads a blob, checking its digest.
+    pub fn blob(&self, digest: &str) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        let (algorithm, hex_digest) = digest
+            .split_once(':')
+            .ok_or(format!("InvalidDigest: {}", digest))?;
+        if algorithm != "sha256" || hex_digest.contains(['/', '.']) {
+            return Err(format!("UnsupportedDigest: {}", digest).into());
+        }
+        let content = std::fs::read(self.dir.join("blobs").join(algorithm).join(hex_digest))?;
+        if sha256_digest(&content) != digest {
+            return Err(format!("ChecksumMismatch: blob {} is corrupt", digest).into());
+        }
+        Ok(content)
+    }
+
+    /// The manifest or index to push: the entry named `ref_name`, the only entry,
+    /// or a multi-arch index of all entries.
+    pub fn root(&self, ref_name: Option<&str>) -> Result<Root, Box<dyn std::error::Error>> {
+        if let Some(ref_name) = ref_name {
+            return self
+                .index
+                .manifests
+                .iter()
+                .find(|d| d.ref_name() == Some(ref_name))
+                .map(|d| Root::Descriptor(d.clone()))
+                .ok_or(format!("ImageNotFound: no {} in the layout", ref_name).into());
+        }
+        match self.index.manifests.as_slice() {
+            [] => Err("InvalidLayout: index.json lists no manifests".into()),
+            [descriptor] => Ok(Root::Descriptor(descriptor.clone())),
+            _ => Ok(Root::LayoutIndex),
+        }
+    }
+}
+
+/// What gets tagged by a push.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Root {
+    /// A manifest or index stored in the blobs
+    Descriptor(Descriptor),
+    /// An index of all entries of `index.json`
+    LayoutIndex,
+}
+
+pub fn sha256_digest(content: &[u8]) -> String {
+    format!("sha256:{}", hex::encode(Sha256::digest(content)))
+}
+
+/// `[scheme://]host[:port]/repository[:tag]`, https unless a scheme is given.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImageReference {
+    pub registry: Url,
+    pub repository: String,
+    pub tag: Option<String>,
+}
+
+impl ImageReference {
+    pub fn parse(image: &str) -> Result<Self, String> {
+        let (scheme, rest) = match image.split_once("://") {
+            Some((scheme, rest)) => (scheme, rest),
+            None => ("https", image),
+        };
+        let invalid = || {
+            format!(
+                "InvalidImage: expected host/repository[:tag], got {}",
+                image
+            )
+        };
+        let (host, path) = rest.split_once('/').ok_or_else(invalid)?;
+        let (repository, tag) = match path.rsplit_once(':') {
+            Some((repository, tag)) if !tag.contains('/') => (repository, Some(tag.to_string())),
+            _ => (path, None),
+        };
+        if host.is_empty() || repository.is_empty() {
+            return Err(invalid());
+        }
+        let registry = Url::parse(&format!("{}://{}/", scheme, host)).map_err(|_| invalid())?;
+        Ok(Self {
+            registry,
+            repository: repository.to_string(),
+            tag,
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Auth {
+    Anonymous,
+    Basic(String, String),
+    Bearer(String),
+}
+
+/// A client for one repository of a registry.
+#[derive(Debug, Clone)]
+pub struct RegistryClient {
+    client: Client,
+    registry: Url,
+    repository: String,
+    credentials: Option<(String, String)>,
+    auth: Auth,
+}
+
+impl RegistryClient {
+    pub fn new(client: &Client, registry: &Url, repository: &str) -> Self {
+        Self {
+            client: client.clone(),
+            registry: registry.clone(),
+            repository: repository.to_string(),
+            credentials: None,
+            auth: Auth::Anonymous,
+        }
+    }
+
+    /// Username and password (for GitLab any username and a token with `write_registry`).
+    pub fn credentials(mut self, username: &str, password: &str) -> Self {
+        self.credentials = Some((username.to_string(), password.to_string()));
+        self
+    }
+
+    fn url(&self, path: &str) -> Result<Url, Box<dyn std::error::Error>> {
+        Ok(self
+            .registry
+            .join(&format!("/v2/{}/{}", &self.repository, path))?)
+    }
+
+    fn with_auth(&self, request: RequestBuilder) -> RequestBuilder {
+        match &self.auth {
+            Auth::Anonymous => request,
+            Auth::Basic(username, password) => request.basic_auth(username, Some(password)),
+            Auth::Bearer(token) => request.bearer_auth(token),
+        }
+    }
+
+    /// Answers the challenge of the registry, getting a push token if needed.
+    pub async fn authenticate(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        let response = self.client.get(self.registry.join("/v2/")?).send().await?;
+        if response.status() != StatusCode::UNAUTHORIZED {
+            return Ok(());
+        }
+        let challenge = response
+            .headers()
+            .get(WWW_AUTHENTICATE)
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or_default()
+            .to_string();
+        let (username, password) = self
+            .credentials
+            .clone()
+            .ok_or("Unauthorized: the registry requires credentials")?;
+        if challenge.to_lowercase().starts_with("basic") {
+            self.auth = Auth::Basic(username, password);
+            return Ok(());
+        }
+        let params = parse_challenge(&challenge);
+        let realm = params
+            .get("realm")
+            .ok_or(format!("Unauthorized: unsupported challenge {}", challenge))?;
+        let mut query = vec![(
+            "scope",
+            format!("repository:{}:pull,push", &self.repository),
+        )];
+        if let Some(service) = params.get("service") {
+            query.push(("service", service.clone()));
+        }
+        let response = self
+            .client
+            .get(Url::parse_with_params(realm, &query)?)
+            .basic_auth(username, Some(password))
+            .send()
+            .await?;
+        let status = response.status();
+        if !status.is_success() {
+            return Err(format!("Getting a registry token failed with status: {}", status).into());
+        }
+        let token: TokenResponse = response.json().await?;
+        self.auth = Auth::Bearer(
+            token
+                .token
+                .or(token.access_token)
+                .ok_or("Unauthorized: no token in the response")?,
+        );
+        Ok(())
+    }
+
+    pub async fn blob_exists(&self, digest: &str) -> Result<bool, Box<dyn std::error::Error>> {
+        let url = self.url(&format!("blobs/{}", digest))?;
+        let response = self.with_auth(self.client.head(url)).send().await?;
+        Ok(response.status().is_success())
+    }
+
+    /// Uploads a blob in one request, after starting an upload session.
+    pub async fn push_blob(
+        &self,
+        digest: &str,
+        content: Vec<u8>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let response = self
+            .with_auth(self.client.post(self.url("blobs/uploads/")?))
+            .send()
+            .await?;
+        let status = response.status();
+        if status != StatusCode::ACCEPTED {
+            return Err(format!(
+                "Starting the upload of {} failed with status: {}, and message: {}",
+                digest,
+                status,
+                response.text().await?
+            )
+            .into());
+        }
+        let location = response
+            .headers()
+            .get(LOCATION)
+            .and_then(|v| v.to_str().ok())
+            .ok_or("No upload location in the response")?;
+        // the location may be relative and may carry its own query
+        let mut url = self.registry.join(location)?;
+        url.query_pairs_mut().append_pair("digest", digest);
+        let response = self
+            .with_auth(self.client.put(url))
+            .header(CONTENT_TYPE, "application/octet-stream")
+            .body(content)
+            .send()
+            .await?;
+        let status = response.status();
+        if status != StatusCode::CREATED {
+            return Err(format!(
+                "Upload of {} failed with status: {}, and message: {}",
+                digest,
+                status,
+                response.text().await?
+            )
+            .into());
+        }
+        Ok(())
+    }
+
+    /// Puts a manifest or index under a tag or its digest.
+    pub async fn push_manifest(
+        &self,
+        reference: &str,
+        media_type: &str,
+        content: Vec<u8>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let url = self.url(&format!("manifests/{}", reference))?;
+        let response = self
+            .with_auth(self.client.put(url))
+            .header(CONTENT_TYPE, media_type)
+            .body(content)
+            .send()
+            .await?;
+        let status = response.status();
+        if status != StatusCode::CREATED {
+            return Err(format!(
+                "Pushing manifest {} failed with status: {}, and message: {}",
+                reference,
+                status,
+                response.text().await?
+            )
+            .into());
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    token: Option<String>,
+    access_token: Option<String>,
+}
+
+/// Parses the `key="value"` parameters of a `WWW-Authenticate` header.
+fn parse_challenge(challenge: &str) -> BTreeMap<String, String> {
+    let param = Regex::new(r#"(\w+)="([^"]*)""#).unwrap();
+    param
+        .captures_iter(challenge)
+        .map(|c| (c[1].to_string(), c[2].to_string()))
+        .collect()
+}
+
+/// Result of a push.
+#[derive(Debug, Serialize)]
+pub struct PushOutput {
+    pub repository: String,
+    pub tags: Vec<String>,
+    /// Digest of the tagged manifest or index
+    pub digest: String,
+    pub media_type: String,
+    pub manifests: usize,
+    pub blobs_uploaded: usize,
+    pub blobs_existing: usize,
+}
+
+/// Pushes the root of `layout` (see [`OciLayout::root`]) under every tag in `tags`.
+pub async fn push_layout(
+    layout: &OciLayout,
+    client: &RegistryClient,
+    root: Root,
+    tags: &[String],
+) -> Result<PushOutput, Box<dyn std::error::Error>> {
+    if tags.is_empty() {
+        return Err("No tag given".into());
+    }
+    let (media_type, root_content) = match root {
+        Root::Descriptor(descriptor) => (
+            descriptor.media_type.clone(),
+            layout.blob(&descriptor.digest)?,
+        ),
+        Root::LayoutIndex => (
+            MEDIA_TYPE_INDEX.to_string(),
+            serde_json::to_vec(&serde_json::json!({
+                "schemaVersion": 2,
+                "mediaType": MEDIA_TYPE_INDEX,
+                "manifests": &layout.index.manifests,
+            }))?,
+        ),
+    };
+    let manifests = if media_type == MEDIA_TYPE_INDEX || media_type == MEDIA_TYPE_DOCKER_LIST {
+        let index: ImageIndex = serde_json::from_slice(&root_content)?;
+        if let Some(nested) = index.manifests.iter().find(|d| d.is_index()) {
+            return Err(format!("UnsupportedLayout: nested index {}", nested.digest).into());
+        }
+        index.manifests
+    } else {
+        vec![]
+    };
+
+    let mut uploaded = 0;
+    let mut existing = 0;
+    let mut done = BTreeSet::new();
+    let image_manifests = match manifests.is_empty() {
+        true => vec![(media_type.clone(), root_content.clone())],
+        false => manifests
+            .iter()
+            .map(|d| Ok((d.media_type.clone(), layout.blob(&d.digest)?)))
+            .collect::<Result<Vec<_>, Box<dyn std::error::Error>>>()?,
+    };
+    for (_, content) in &image_manifests {
+        let manifest: ImageManifest = serde_json::from_slice(content)?;
+        for blob in std::iter::once(&manifest.config).chain(&manifest.layers) {
+            if !done.insert(blob.digest.clone()) {
+                continue;
+            }
+            if client.blob_exists(&blob.digest).await? {
+                existing += 1;
+                continue;
+            }
+            eprintln!("Uploading blob {} ({} bytes)", &blob.digest, blob.size);
+            client
+                .push_blob(&blob.digest, layout.blob(&blob.digest)?)
+                .await?;
+            uploaded += 1;
+        }
+    }
+    // the manifests of an index are pushed by digest before the index refers to them
+    if !manifests.is_empty() {
+        for (media_type, content) in image_manifests {
+            let digest = sha256_digest(&content);
+            eprintln!("Pushing manifest {}", &digest);
+            client.push_manifest(&digest, &media_type, content).await?;
+        }
+    }
+    for tag in tags {
+        eprintln!("Pushing {}:{}", &client.repository, tag);
+        client
+            .push_manifest(tag, &media_type, root_content.clone())
+            .await?;
+    }
+    Ok(PushOutput {
+        repository: client.repository.clone(),
+        tags: tags.to_vec(),
+        digest: sha256_digest(&root_content),
+        media_type,
+        manifests: manifests.len().max(1),
+        blobs_uploaded: uploaded,
+        blobs_existing: existing,
+    })
+}
+
+#[cfg(test)]
+mod oci_tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::sync::{Arc, Mutex};
+
+    /// Blobs by digest and manifests by reference.
+    type Store = Arc<Mutex<HashMap<String, Vec<u8>>>>;
+
+    /// A registry:2 stand-in behind a `/jwt/auth` token realm, one request per connection.
+    fn stand_in_registry() -> (Url, Store) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        let store = Arc::new(Mutex::new(HashMap::new()));
+        let stored = store.clone();
+        let realm = format!("{}/jwt/auth", base);
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                let parts: Vec<&str> = line.split_whitespace().collect();
+                let (method, target) = (parts[0].to_string(), parts[1].to_string());
+                let mut headers = HashMap::new();
+                loop {
+                    let mut header = String::new();
+                    reader.read_line(&mut header).unwrap();
+                    match header.trim_end().split_once(": ") {
+                        Some((k, v)) => headers.insert(k.to_lowercase(), v.to_string()),
+                        None => break,
+                    };
+                }
+                let length = headers
+                    .get("content-length")
+                    .map_or(0, |l| l.parse().unwrap());
+                let mut body = vec![0; length];
+                reader.read_exact(&mut body).unwrap();
+                let authorized =
+                    headers.get("authorization").map(String::as_str) == Some("Bearer pushtoken");
+                let path = target.split('?').next().unwrap().to_string();
+                let (status, extra, response) = match (method.as_str(), path.as_str()) {
+                    (_, "/jwt/auth") if headers.contains_key("authorization") => {
+                        (200, String::new(), r#"{"token":"pushtoken"}"#.to_string())
+                    }
+                    _ if !authorized => (
+                        401,
+                        format!(
+                            "WWW-Authenticate: Bearer realm=\"{}\",service=\"container_registry\"\r\n",
+                            realm
+                        ),
+                        String::new(),
+                    ),
+                    ("GET", "/v2/") => (200, String::new(), String::new()),
+                    ("HEAD", p) => {
+                        let digest = p.rsplit('/').next().unwrap();
+                        let found = stored.lock().unwrap().contains_key(digest);
+                        (if found { 200 } else { 404 }, String::new(), String::new())
+                    }
+                    ("POST", _) => (
+                        202,
+                        "Location: /v2/upload/1?state=x\r\n".to_string(),
+                        String::new(),
+                    ),
+                    ("PUT", p) if p.starts_with("/v2/upload/") => {
+                        let digest = target.rsplit("digest=").next().unwrap();
+                        let digest = digest.replace("%3A", ":");
+                        assert_eq!(sha256_digest(&body), digest);
+                        stored.lock().unwrap().insert(digest, body);
+                        (201, String::new(), String::new())
+                    }
+                    ("PUT", p) => {
+                        let reference = p.rsplit('/').next().unwrap().to_string();
+                        stored.lock().unwrap().insert(reference, body);
+                        (201, String::new(), String::new())
+                    }
+                    _ => (404, String::new(), String::new()),
+                };
+                write!(
+                    stream,
+                    "HTTP/1.1 {} X\r\n{}Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    status,
+                    extra,
+                    response.len(),
+                    response
+                )
+                .unwrap();
+            }
+        });
+        (Url::parse(&base).unwrap(), store)
+    }
+
+    /// Writes a blob into the layout, returning its descriptor.
+    fn write_blob(dir: &Path, media_type: &str, content: &[u8]) -> Descriptor {
+        let digest = sha256_digest(content);
+        let blobs = dir.join("blobs/sha256");
+        std::fs::create_dir_all(&blobs).unwrap();
+        std::fs::write(blobs.join(&digest[7..]), content).unwrap();
+        Descriptor {
+            media_type: media_type.to_string(),
+            digest,
+            size: content.len() as u64,
+            annotations: BTreeMap::new(),
+            platform: None,
+        }
+    }
+
+    #[test]
+    fn test_image_reference() {
+        let image = ImageReference::parse("registry.gitlab.com/puterize/glabu:abc-amd64").unwrap();
+        assert_eq!(image.registry.as_str(), "https://registry.gitlab.com/");
+        assert_eq!(image.repository, "puterize/glabu");
+        assert_eq!(image.tag.as_deref(), Some("abc-amd64"));
+        let image = ImageReference::parse("http://localhost:5000/glabu").unwrap();
+        assert_eq!(image.registry.as_str(), "http://localhost:5000/");
+        assert_eq!(image.tag, None);
+        assert!(ImageReference::parse("glabu").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_push_layout() {
+        let dir = std::env::temp_dir().join(format!("glabu-oci-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let layer = write_blob(
+            &dir,
+            "application/vnd.oci.image.layer.v1.tar+gzip",
+            b"layer",
+        );
+        let mut manifests = vec![];
+        for arch in ["amd64", "arm64"] {
+            let config = write_blob(
+                &dir,
+                "application/vnd.oci.image.config.v1+json",
+                format!(r#"{{"architecture":"{}"}}"#, arch).as_bytes(),
+            );
+            let manifest = serde_json::json!({
+                "schemaVersion": 2,
+                "mediaType": MEDIA_TYPE_MANIFEST,
+                "config": config,
+                "layers": [&layer],
+            });
+            let mut descriptor = write_blob(
+                &dir,
+                MEDIA_TYPE_MANIFEST,
+                &serde_json::to_vec(&manifest).unwrap(),
+            );
+            descriptor.platform = Some(Platform {
+                architecture: arch.to_string(),
+                os: "linux".to_string(),
+                variant: None,
+            });
+            manifests.push(descriptor);
+        }
+        std::fs::write(dir.join("oci-layout"), r#"{"imageLayoutVersion":"1.0.0"}"#).unwrap();
+        let index = serde_json::json!({"schemaVersion": 2, "manifests": manifests});
+        std::fs::write(dir.join("index.json"), index.to_string()).unwrap();
+
+        let (registry, store) = stand_in_registry();
+        let layout = OciLayout::open(&dir).unwrap();
+        let root = layout.root(None).unwrap();
+        assert_eq!(root, Root::LayoutIndex);
+        let mut client = RegistryClient::new(&Client::new(), &registry, "puterize/glabu")
+            .credentials("glabu", "secret");
+        client.authenticate().await.unwrap();
+        let output = push_layout(&layout, &client, root, &["abc".to_string()])
+            .await
+            .unwrap();
+        assert_eq!(output.manifests, 2);
+        // the layer is shared by both images
+        assert_eq!(output.blobs_uploaded, 3);
+        assert_eq!(output.media_type, MEDIA_TYPE_INDEX);
+        let store = store.lock().unwrap();
+        assert!(store.contains_key(&manifests[1].digest));
+        assert_eq!(sha256_digest(&store["abc"]), output.digest);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
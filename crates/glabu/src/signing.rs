@@ -0,0 +1,257 @@
+//! Minisign compatible signing and verification of package files.
+//!
+//! Signatures are stored as `<file>.minisig` sidecars next to the signed file
+//! in the same package version. Keys are the ones created by `minisign -G`,
+//! encrypted secret keys are decrypted with the password from `GLABU_MINISIGN_PASSWORD`,
+//! and the trusted public key can be given in `GLABU_PUBLIC_KEY`.
+//! See https://jedisct1.github.io/minisign/ for the formats.
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use blake2::{Blake2b512, Digest, digest::consts::U32};
+use ed25519_dalek::{Signer, SigningKey};
+use std::fmt;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub use minisign_verify::PublicKey;
+
+/// Suffix of signature sidecar files.
+pub const SIGNATURE_SUFFIX: &str = ".minisig";
+/// Environment variable holding the trusted public key (a path or base64 string).
+pub const PUBLIC_KEY_ENV: &str = "GLABU_PUBLIC_KEY";
+/// Environment variable holding the password of an encrypted secret key.
+pub const PASSWORD_ENV: &str = "GLABU_MINISIGN_PASSWORD";
+
+const SIG_ALG: &[u8; 2] = b"Ed";
+const SIG_ALG_PREHASHED: &[u8; 2] = b"ED";
+const KDF_SCRYPT: &[u8; 2] = b"Sc";
+const KDF_NONE: &[u8; 2] = &[0, 0];
+const SECRET_KEY_LEN: usize = 158;
+
+/// A minisign secret key.
+#[derive(Clone)]
+pub struct SecretKey {
+    key_id: [u8; 8],
+    signing_key: SigningKey,
+}
+
+impl fmt::Debug for SecretKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SecretKey")
+            .field("key_id", &hex::encode_upper(self.key_id))
+            .finish_non_exhaustive()
+    }
+}
+
+impl SecretKey {
+    pub fn new(key_id: [u8; 8], signing_key: SigningKey) -> Self {
+        Self {
+            key_id,
+            signing_key,
+        }
+    }
+
+    /// Reads a secret key file as written by `minisign -G`.
+    pub fn from_file<P: AsRef<Path>>(
+        path: P,
+        password: Option<&str>,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        Self::decode(&std::fs::read_to_string(path)?, password)
+    }
+
+    /// Decodes the content of a secret key file.
+    pub fn decode(
+        content: &str,
+        password: Option<&str>,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let encoded = content
+            .lines()
+            .nth(1)
+            .ok_or("InvalidSecretKey: missing key line")?;
+        let bin = BASE64.decode(encoded.trim())?;
+        if bin.len() != SECRET_KEY_LEN || &bin[0..2] != SIG_ALG {
+            return Err("InvalidSecretKey: not an ed25519 minisign secret key".into());
+        }
+        let kdf_alg = &bin[2..4];
+        let salt = &bin[6..38];
+        let opslimit = u64::from_le_bytes(bin[38..46].try_into()?);
+        let memlimit = u64::from_le_bytes(bin[46..54].try_into()?);
+        let mut keynum_sk = bin[54..].to_vec();
+        if kdf_alg == KDF_SCRYPT {
+            let password = password.ok_or(format!(
+                "The secret key is encrypted, set {} to its password",
+                PASSWORD_ENV
+            ))?;
+            xor_keystream(password, salt, opslimit, memlimit, &mut keynum_sk)?;
+        } else if kdf_alg != KDF_NONE {
+            return Err("InvalidSecretKey: unsupported key derivation".into());
+        }
+        let key_id: [u8; 8] = keynum_sk[0..8].try_into()?;
+        let secret: [u8; 32] = keynum_sk[8..40].try_into()?;
+        let checksum = &keynum_sk[72..104];
+        let mut hasher = blake2::Blake2b::<U32>::new();
+        hasher.update(SIG_ALG);
+        hasher.update(key_id);
+        hasher.update(&keynum_sk[8..72]);
+        if hasher.finalize().as_slice() != checksum {
+            return Err("InvalidSecretKey: wrong password or corrupted key".into());
+        }
+        Ok(Self::new(key_id, SigningKey::from_bytes(&secret)))
+    }
+
+    /// The matching public key.
+    pub fn public_key(&self) -> Result<PublicKey, Box<dyn std::error::Error>> {
+        let mut bin = SIG_ALG.to_vec();
+        bin.extend_from_slice(&self.key_id);
+        bin.extend_from_slice(self.signing_key.verifying_key().as_bytes());
+        Ok(PublicKey::from_base64(&BASE64.encode(bin))?)
+    }
+
+    /// Signs `content` and returns the content of the `.minisig` file.
+    pub fn sign(&self, content: &[u8], file_name: &str) -> String {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+        let trusted_comment = format!("timestamp:{}\tfile:{}\tprehashed", timestamp, file_name);
+        let signature = self.signing_key.sign(&Blake2b512::digest(content));
+        let mut signature_bin = SIG_ALG_PREHASHED.to_vec();
+        signature_bin.extend_from_slice(&self.key_id);
+        signature_bin.extend_from_slice(&signature.to_bytes());
+        let mut global = signature.to_bytes().to_vec();
+        global.extend_from_slice(trusted_comment.as_bytes());
+        let global_signature = self.signing_key.sign(&global);
+        format!(
+            "untrusted comment: signature from glabu secret key\n{}\ntrusted comment: {}\n{}\n",
+            BASE64.encode(signature_bin),
+            trusted_comment,
+            BASE64.encode(global_signature.to_bytes())
+        )
+    }
+}
+
+/// Encrypts or decrypts the key material of a secret key by XORing it with
+/// a keystream derived from the password with scrypt.
+fn xor_keystream(
+    password: &str,
+    salt: &[u8],
+    opslimit: u64,
+    memlimit: u64,
+    keynum_sk: &mut [u8],
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut stream = vec![0u8; keynum_sk.len()];
+    let params = scrypt_params(opslimit, memlimit)?;
+    scrypt::scrypt(password.as_bytes(), salt, &params, &mut stream)
+        .map_err(|e| format!("InvalidSecretKey: {}", e))?;
+    keynum_sk
+        .iter_mut()
+        .zip(stream)
+        .for_each(|(byte, key)| *byte ^= key);
+    Ok(())
+}
+
+/// Scrypt parameters from libsodium's opslimit and memlimit, as minisign does.
+/// The `len` of the params is only checked, not used: the keystream is longer than
+/// the 64 bytes allowed there, and its length comes from the output buffer.
+fn scrypt_params(
+    opslimit: u64,
+    memlimit: u64,
+) -> Result<scrypt::Params, Box<dyn std::error::Error>> {
+    let opslimit = opslimit.max(32768);
+    let r = 8u32;
+    let log_n_for = |max_n: u64| (1..63u8).find(|n| 1u64 << n > max_n / 2).unwrap_or(63);
+    let (log_n, p) = if opslimit < memlimit / 32 {
+        (log_n_for(opslimit / (r as u64 * 4)), 1)
+    } else {
+        let log_n = log_n_for(memlimit / (r as u64 * 128));
+        let max_rp = ((opslimit / 4) / (1u64 << log_n)).min(0x3fff_ffff) as u32;
+        (log_n, max_rp / r)
+    };
+    Ok(
+        scrypt::Params::new(log_n, r, p, scrypt::Params::RECOMMENDED_LEN)
+            .map_err(|e| format!("InvalidSecretKey: {}", e))?,
+    )
+}
+
+/// Reads a trusted public key, either from a `minisign.pub` file or as a base64 string.
+pub fn read_public_key(key: &str) -> Result<PublicKey, Box<dyn std::error::Error>> {
+    let public_key = if Path::new(key).is_file() {
+        PublicKey::from_file(key)?
+    } else {
+        PublicKey::from_base64(key)?
+    };
+    Ok(public_key)
+}
+
+/// Verifies `content` against the content of a `.minisig` file.
+pub fn verify(
+    public_key: &PublicKey,
+    content: &[u8],
+    signature: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let signature = minisign_verify::Signature::decode(signature)?;
+    public_key.verify(content, &signature, true)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod signing_tests {
+    use super::*;
+
+    #[test]
+    fn test_sign_and_verify() -> Result<(), Box<dyn std::error::Error>> {
+        let secret_key = SecretKey::new([1; 8], SigningKey::from_bytes(&[7; 32]));
+        let public_key = secret_key.public_key()?;
+        let signature = secret_key.sign(b"glabu binary", "glabu-x86_64");
+        verify(&public_key, b"glabu binary", &signature)?;
+        assert!(verify(&public_key, b"tampered binary", &signature).is_err());
+        let other_key = SecretKey::new([2; 8], SigningKey::from_bytes(&[8; 32])).public_key()?;
+        assert!(verify(&other_key, b"glabu binary", &signature).is_err());
+        Ok(())
+    }
+
+    /// Key id, keypair and checksum of a secret key, before encryption.
+    fn keynum_sk(key_id: [u8; 8]) -> Vec<u8> {
+        let signing_key = SigningKey::from_bytes(&[7; 32]);
+        let mut keynum_sk = key_id.to_vec();
+        keynum_sk.extend_from_slice(&signing_key.to_keypair_bytes());
+        let mut hasher = blake2::Blake2b::<U32>::new();
+        hasher.update(SIG_ALG);
+        hasher.update(&keynum_sk);
+        keynum_sk.extend_from_slice(&hasher.finalize());
+        keynum_sk
+    }
+
+    #[test]
+    fn test_decode_unencrypted_secret_key() -> Result<(), Box<dyn std::error::Error>> {
+        let key_id = [1u8; 8];
+        let mut bin = b"Ed\0\0B2".to_vec();
+        bin.extend_from_slice(&[0; 48]);
+        bin.extend_from_slice(&keynum_sk(key_id));
+        let content = format!("untrusted comment: test key\n{}\n", BASE64.encode(bin));
+        let secret_key = SecretKey::decode(&content, None)?;
+        assert_eq!(secret_key.key_id, key_id);
+        Ok(())
+    }
+
+    #[test]
+    fn test_decode_encrypted_secret_key() -> Result<(), Box<dyn std::error::Error>> {
+        let key_id = [3u8; 8];
+        let salt = [9u8; 32];
+        // far below the limits of `minisign -G`, to keep the test fast
+        let (opslimit, memlimit) = (1u64 << 15, 1u64 << 20);
+        let mut encrypted = keynum_sk(key_id);
+        xor_keystream("secret", &salt, opslimit, memlimit, &mut encrypted)?;
+        let mut bin = b"EdScB2".to_vec();
+        bin.extend_from_slice(&salt);
+        bin.extend_from_slice(&opslimit.to_le_bytes());
+        bin.extend_from_slice(&memlimit.to_le_bytes());
+        bin.extend_from_slice(&encrypted);
+        let content = format!("untrusted comment: test key\n{}\n", BASE64.encode(bin));
+        let secret_key = SecretKey::decode(&content, Some("secret"))?;
+        assert_eq!(secret_key.key_id, key_id);
+        assert!(SecretKey::decode(&content, Some("wrong")).is_err());
+        Ok(())
+    }
+}
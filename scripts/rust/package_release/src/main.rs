@@ -52,9 +52,15 @@ pub fn build_compress_and_upload() -> Result<()> {
     }
     let binary_for_current_arch = binary_for_current_arch.unwrap();
 
-    for arch in archs {
+    for (i, arch) in archs.iter().enumerate() {
         let target_triple = format!("{}-unknown-linux-musl", arch);
         let binary_path = PathBuf::from(format!("./target/{target_triple}/release/glabu"));
+        // SHA256SUMS lists every file of the version, so it is only uploaded with the last binary
+        let sha256sums: &[&str] = if i + 1 == archs.len() {
+            &["--sha256sums"]
+        } else {
+            &[]
+        };
         // Upload to GitLab
         let file_name = binary_path
             .file_name()
@@ -63,7 +69,7 @@ pub fn build_compress_and_upload() -> Result<()> {
         println!("Uploading the binary {} to the gitlab...", &file_name);
         cmd!(
             sh,
-            "{binary_for_current_arch} package-upload puterize/prebuilt --package-name glabu --package-version {commit_hash} --file-name {file_name}-{arch} --file-path {binary_path}"
+            "{binary_for_current_arch} package-upload puterize/prebuilt --package-name glabu --package-version {commit_hash} --file-name {file_name}-{arch} --file-path {binary_path} {sha256sums...}"
         )
         .run()
         .context("Failed to upload binary to GitLab")?;
@@ -77,9 +83,11 @@ If you want to install glabu globally, run the following command:
 	"###
     );
     println!(
-        "sudo install {} /usr/local/bin/glabu",
-        binary_for_current_arch.display()
+        "sudo -E {} install puterize/prebuilt glabu --package-version {} --bin-dir /usr/local/bin",
+        binary_for_current_arch.display(),
+        commit_hash
     );
+    println!("An installed glabu can be updated later with: glabu self-update");
 
     Ok(())
 }